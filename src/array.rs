@@ -0,0 +1,129 @@
+//! Whole-stripe encoding and decoding of 2-D `uint8` arrays, one shard per
+//! row. The `numpy` module is only imported at runtime to allocate results.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use reed_solomon_simd::{ReedSolomonDecoder, ReedSolomonEncoder};
+
+use crate::buffer::{Shard, ShardMut};
+use crate::error::Error;
+
+/// Returns `(rows, columns)` of a 2-D array.
+fn shape_2d(arr: &Shard) -> PyResult<(usize, usize)> {
+    match *arr.shape() {
+        [rows, columns] => Ok((rows, columns)),
+        ref shape => Err(PyValueError::new_err(format!(
+            "expected a 2-D array, got {} dimensions",
+            shape.len()
+        ))),
+    }
+}
+
+fn empty_array(py: Python<'_>, rows: usize, columns: usize) -> PyResult<(PyObject, ShardMut)> {
+    let array = py
+        .import("numpy")?
+        .call_method1("empty", ((rows, columns), "uint8"))?;
+    Ok((array.into(), array.extract()?))
+}
+
+/// Encodes an `(original_count, shard_bytes)` array, returning the
+/// `(recovery_count, shard_bytes)` array of recovery shards.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+pub(crate) fn encode_array(
+    py: Python<'_>,
+    arr: Shard,
+    recovery_count: usize,
+) -> PyResult<PyObject> {
+    let (original_count, shard_bytes) = shape_2d(&arr)?;
+
+    let mut encoder = ReedSolomonEncoder::new(original_count, recovery_count, shard_bytes)
+        .map_err(Error::from)?;
+    let (recovery, mut recovery_out) = empty_array(py, recovery_count, shard_bytes)?;
+
+    py.allow_threads(|| {
+        for original_shard in arr.as_ref().chunks_exact(shard_bytes) {
+            encoder.add_original_shard(original_shard)?;
+        }
+        let encoder_result = encoder.encode()?;
+
+        for (out, recovery_shard) in recovery_out
+            .as_mut()
+            .chunks_exact_mut(shard_bytes)
+            .zip(encoder_result.recovery_iter())
+        {
+            out.copy_from_slice(recovery_shard);
+        }
+        Ok(())
+    })
+    .map_err(Error)?;
+
+    Ok(recovery)
+}
+
+/// Decodes a stripe given as an `(original_count + recovery_count,
+/// shard_bytes)` array, original shards first, where `present_mask[i]` tells
+/// whether row `i` holds a received shard. Rows of missing shards are
+/// ignored. Returns the `(original_count, shard_bytes)` array of original
+/// shards.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+pub(crate) fn decode_array(
+    py: Python<'_>,
+    original_count: usize,
+    recovery_count: usize,
+    arr: Shard,
+    present_mask: &PyAny,
+) -> PyResult<PyObject> {
+    let (rows, shard_bytes) = shape_2d(&arr)?;
+    let shard_count = original_count.checked_add(recovery_count).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "original_count {original_count} + recovery_count {recovery_count} overflows"
+        ))
+    })?;
+    if rows != shard_count {
+        return Err(PyValueError::new_err(format!(
+            "expected {shard_count} rows (original_count + recovery_count), got {rows}"
+        )));
+    }
+
+    let present: Shard = py
+        .import("numpy")?
+        .call_method1("ascontiguousarray", (present_mask, "uint8"))?
+        .extract()?;
+    let present = present.as_ref();
+    if present.len() != rows {
+        return Err(PyValueError::new_err(format!(
+            "present_mask has {} entries, expected {rows}",
+            present.len()
+        )));
+    }
+
+    let mut decoder = ReedSolomonDecoder::new(original_count, recovery_count, shard_bytes)
+        .map_err(Error::from)?;
+    let (original, mut original_out) = empty_array(py, original_count, shard_bytes)?;
+
+    py.allow_threads(|| {
+        let shards = arr.as_ref().chunks_exact(shard_bytes);
+        let original_out = original_out.as_mut();
+
+        for (idx, shard) in shards.enumerate().filter(|&(idx, _)| present[idx] != 0) {
+            if idx < original_count {
+                decoder.add_original_shard(idx, shard)?;
+                original_out[idx * shard_bytes..][..shard_bytes].copy_from_slice(shard);
+            } else {
+                decoder.add_recovery_shard(idx - original_count, shard)?;
+            }
+        }
+
+        let decoder_result = decoder.decode()?;
+        for (idx, restored_shard) in decoder_result.restored_original_iter() {
+            original_out[idx * shard_bytes..][..shard_bytes].copy_from_slice(restored_shard);
+        }
+        Ok(())
+    })
+    .map_err(Error)?;
+
+    Ok(original)
+}
@@ -0,0 +1,205 @@
+//! Encoding of arbitrary-length blobs, which are split into equally sized,
+//! zero-padded original shards.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+
+use reed_solomon_simd::{ReedSolomonDecoder, ReedSolomonEncoder};
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::buffer::Shard;
+use crate::error::Error;
+
+/// Smallest valid shard size which fits `length` bytes into
+/// `original_count` shards. Shards must be non-empty and of even length.
+pub(crate) fn shard_bytes_for(length: usize, original_count: usize) -> usize {
+    let shard_bytes = length.div_ceil(original_count.max(1));
+    (shard_bytes + shard_bytes % 2).max(2)
+}
+
+/// Original shards of `data`, the last ones zero-padded to `shard_bytes`.
+pub(crate) fn split_padded(
+    data: &[u8],
+    original_count: usize,
+    shard_bytes: usize,
+) -> impl Iterator<Item = Cow<'_, [u8]>> {
+    let mut chunks = data.chunks(shard_bytes);
+    (0..original_count).map(move |_| match chunks.next() {
+        Some(chunk) if chunk.len() == shard_bytes => chunk.into(),
+        Some(chunk) => {
+            let mut padded = chunk.to_vec();
+            padded.resize(shard_bytes, 0);
+            padded.into()
+        }
+        None => vec![0; shard_bytes].into(),
+    })
+}
+
+/// Stripe parameters needed to decode a blob, as returned by `encode_bytes`.
+pub(crate) struct Metadata {
+    pub(crate) original_count: usize,
+    pub(crate) recovery_count: usize,
+    pub(crate) shard_bytes: usize,
+    pub(crate) length: usize,
+}
+
+impl Metadata {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("original_count", self.original_count)?;
+        dict.set_item("recovery_count", self.recovery_count)?;
+        dict.set_item("shard_bytes", self.shard_bytes)?;
+        dict.set_item("length", self.length)?;
+        Ok(dict)
+    }
+
+    fn from_dict(dict: &PyDict) -> PyResult<Self> {
+        let get = |key: &str| -> PyResult<usize> {
+            dict.get_item(key)?
+                .ok_or_else(|| PyValueError::new_err(format!("metadata is missing '{key}'")))?
+                .extract()
+        };
+
+        let metadata = Self {
+            original_count: get("original_count")?,
+            recovery_count: get("recovery_count")?,
+            shard_bytes: get("shard_bytes")?,
+            length: get("length")?,
+        };
+
+        let capacity = metadata.original_count.checked_mul(metadata.shard_bytes);
+        if capacity.is_some_and(|capacity| metadata.length > capacity) {
+            return Err(PyValueError::new_err(format!(
+                "metadata length {} does not fit in {} original shards of {} bytes",
+                metadata.length, metadata.original_count, metadata.shard_bytes
+            )));
+        }
+
+        Ok(metadata)
+    }
+}
+
+/// Reassembles the first `length` bytes of the original shards, looking up
+/// each (received or restored) shard by index with `original_shard`.
+pub(crate) fn assemble<'a>(
+    py: Python<'_>,
+    length: usize,
+    shard_bytes: usize,
+    mut original_shard: impl FnMut(usize) -> Option<&'a [u8]>,
+) -> PyResult<Py<PyBytes>> {
+    let blob = PyBytes::new_with(py, length, |blob| {
+        for (idx, chunk) in blob.chunks_mut(shard_bytes).enumerate() {
+            let shard = original_shard(idx).expect("original shard is either received or restored");
+            chunk.copy_from_slice(&shard[..chunk.len()]);
+        }
+        Ok(())
+    })?;
+    Ok(blob.into())
+}
+
+/// Splits `data` into `original_count` original shards and generates
+/// `recovery_count` recovery shards. Each shard is turned into a Python
+/// object by `to_py(index, shard)`, in index order with original shards first.
+pub(crate) fn encode_with<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    original_count: usize,
+    recovery_count: usize,
+    mut to_py: impl FnMut(usize, &[u8]) -> PyResult<&'py PyBytes>,
+) -> PyResult<(Vec<&'py PyBytes>, Metadata)> {
+    let metadata = Metadata {
+        original_count,
+        recovery_count,
+        shard_bytes: shard_bytes_for(data.len(), original_count),
+        length: data.len(),
+    };
+
+    let mut encoder = ReedSolomonEncoder::new(original_count, recovery_count, metadata.shard_bytes)
+        .map_err(Error::from)?;
+
+    let original: Vec<_> = split_padded(data, original_count, metadata.shard_bytes).collect();
+
+    let encoder_result = py
+        .allow_threads(|| {
+            for original_shard in &original {
+                encoder.add_original_shard(original_shard)?;
+            }
+            encoder.encode()
+        })
+        .map_err(Error::from)?;
+
+    let shards = original
+        .iter()
+        .map(AsRef::as_ref)
+        .chain(encoder_result.recovery_iter())
+        .enumerate()
+        .map(|(idx, shard)| to_py(idx, shard))
+        .collect::<PyResult<_>>()?;
+
+    Ok((shards, metadata))
+}
+
+/// Splits `blob` into `original_count` original shards and generates
+/// `recovery_count` recovery shards. Returns a list of all shards (original
+/// shards first) together with a metadata dict to pass to `decode_bytes`.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+pub(crate) fn encode_bytes(
+    py: Python<'_>,
+    blob: Shard,
+    original_count: usize,
+    recovery_count: usize,
+) -> PyResult<(Py<PyList>, Py<PyDict>)> {
+    let (shards, metadata) = encode_with(
+        py,
+        blob.as_ref(),
+        original_count,
+        recovery_count,
+        |_, shard| Ok(PyBytes::new(py, shard)),
+    )?;
+
+    Ok((PyList::new(py, shards).into(), metadata.to_dict(py)?.into()))
+}
+
+/// Restores a blob from any `original_count` of the shards returned by
+/// `encode_bytes`, given as a dict from shard index to shard.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+pub(crate) fn decode_bytes(
+    py: Python<'_>,
+    metadata: &PyDict,
+    shards: HashMap<usize, Shard>,
+) -> PyResult<Py<PyBytes>> {
+    let Metadata {
+        original_count,
+        recovery_count,
+        shard_bytes,
+        length,
+    } = Metadata::from_dict(metadata)?;
+
+    let mut decoder = ReedSolomonDecoder::new(original_count, recovery_count, shard_bytes)
+        .map_err(Error::from)?;
+
+    let decoder_result = py
+        .allow_threads(|| {
+            for (&idx, shard) in &shards {
+                if idx < original_count {
+                    decoder.add_original_shard(idx, shard)?;
+                } else {
+                    decoder.add_recovery_shard(idx - original_count, shard)?;
+                }
+            }
+            decoder.decode()
+        })
+        .map_err(Error::from)?;
+
+    assemble(py, length, shard_bytes, |idx| {
+        shards
+            .get(&idx)
+            .map(AsRef::as_ref)
+            .or_else(|| decoder_result.restored_original(idx))
+    })
+}
@@ -0,0 +1,130 @@
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+
+use std::ops::Range;
+
+/// A shard passed in from Python as any object supporting the buffer
+/// protocol with contiguous `u8` contents (`bytes`, `bytearray`,
+/// `memoryview`, `mmap`, `numpy.ndarray` of `uint8`, ...).
+///
+/// Holding the buffer keeps the exporting object from being resized or
+/// freed, so the data can be read after releasing the GIL. The caller must
+/// not mutate a writable buffer from another thread while it is in use.
+pub(crate) struct Shard(PyBuffer<u8>);
+
+impl<'source> FromPyObject<'source> for Shard {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        let buffer = PyBuffer::<u8>::get(ob).map_err(|err| {
+            PyTypeError::new_err(format!(
+                "expected a bytes-like object of unsigned bytes, got '{}': {}",
+                ob.get_type().name().unwrap_or("?"),
+                err.value(ob.py())
+            ))
+        })?;
+
+        if !buffer.is_c_contiguous() {
+            return Err(PyTypeError::new_err("shard buffer must be C-contiguous"));
+        }
+
+        Ok(Self(buffer))
+    }
+}
+
+impl Shard {
+    /// Dimensions of the buffer, e.g. `[rows, columns]` for a 2-D array.
+    #[cfg(feature = "numpy")]
+    pub(crate) fn shape(&self) -> &[usize] {
+        self.0.shape()
+    }
+}
+
+impl AsRef<[u8]> for Shard {
+    fn as_ref(&self) -> &[u8] {
+        let len = self.0.len_bytes();
+        if len == 0 {
+            return &[];
+        }
+
+        // SAFETY: The buffer is C-contiguous, `len` bytes long and stays
+        // exported (and thus alive and unmoved) for as long as `self`.
+        unsafe { std::slice::from_raw_parts(self.0.buf_ptr().cast::<u8>(), len) }
+    }
+}
+
+/// A writable, contiguous Python buffer that output is written into.
+pub(crate) struct ShardMut(PyBuffer<u8>);
+
+impl<'source> FromPyObject<'source> for ShardMut {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        let Shard(buffer) = ob.extract()?;
+
+        if buffer.readonly() {
+            return Err(PyTypeError::new_err(format!(
+                "output buffer must be writable, got read-only '{}'",
+                ob.get_type().name().unwrap_or("?"),
+            )));
+        }
+
+        Ok(Self(buffer))
+    }
+}
+
+impl ShardMut {
+    pub(crate) fn len(&self) -> usize {
+        self.0.len_bytes()
+    }
+
+    pub(crate) fn as_mut(&mut self) -> &mut [u8] {
+        let len = self.len();
+        if len == 0 {
+            return &mut [];
+        }
+
+        // SAFETY: As for `Shard`, and the buffer is writable. Exclusive
+        // access is checked by `ensure_disjoint` before use.
+        unsafe { std::slice::from_raw_parts_mut(self.0.buf_ptr().cast::<u8>(), len) }
+    }
+}
+
+fn addr_range(buffer: &PyBuffer<u8>) -> Range<usize> {
+    let start = buffer.buf_ptr() as usize;
+    start..start + buffer.len_bytes()
+}
+
+/// Fails if any output buffer overlaps another output buffer or one of the
+/// inputs, as writing into it would alias memory that is being read.
+pub(crate) fn ensure_disjoint<'a>(
+    inputs: impl IntoIterator<Item = &'a Shard>,
+    outputs: impl IntoIterator<Item = &'a ShardMut>,
+) -> PyResult<()> {
+    let mut ranges: Vec<(Range<usize>, bool)> = inputs
+        .into_iter()
+        .map(|shard| (addr_range(&shard.0), false))
+        .chain(
+            outputs
+                .into_iter()
+                .map(|shard| (addr_range(&shard.0), true)),
+        )
+        .filter(|(range, _)| !range.is_empty())
+        .collect();
+    ranges.sort_unstable_by_key(|(range, _)| range.start);
+
+    // Ranges are sorted by start, so a range overlaps an earlier one
+    // exactly when it starts before that one's end.
+    let mut any_end = 0;
+    let mut output_end = 0;
+    for (range, is_output) in ranges {
+        if range.start < output_end || (is_output && range.start < any_end) {
+            return Err(PyValueError::new_err(
+                "output buffers must not overlap each other or the input shards",
+            ));
+        }
+        any_end = any_end.max(range.end);
+        if is_output {
+            output_end = output_end.max(range.end);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,367 @@
+//! Opt-in per-shard checksums. A checksum trailer is appended to every
+//! shard, so that corrupted shards can be detected and treated as lost.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+
+use reed_solomon_simd::rate::{DefaultRateDecoder, DefaultRateEncoder};
+
+use std::collections::HashMap;
+
+use crate::buffer::Shard;
+
+#[derive(Clone, Copy)]
+pub(crate) enum Algorithm {
+    Crc32c,
+    Xxh64,
+}
+
+impl<'source> FromPyObject<'source> for Algorithm {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        match ob.extract::<&str>()? {
+            "crc32c" => Ok(Self::Crc32c),
+            "xxh64" => Ok(Self::Xxh64),
+            other => Err(PyValueError::new_err(format!(
+                "unknown checksum algorithm '{other}', expected 'crc32c' or 'xxh64'"
+            ))),
+        }
+    }
+}
+
+impl Algorithm {
+    /// Size of the checksum trailer in bytes.
+    pub(crate) fn trailer_bytes(self) -> usize {
+        match self {
+            Self::Crc32c => 4,
+            Self::Xxh64 => 8,
+        }
+    }
+
+    pub(crate) fn checksum(self, data: &[u8]) -> u64 {
+        match self {
+            Self::Crc32c => u64::from(crc32c(data)),
+            Self::Xxh64 => xxh64(data),
+        }
+    }
+
+    /// Writes `payload` followed by its checksum trailer into `out`.
+    pub(crate) fn write_with_trailer(self, payload: &[u8], out: &mut [u8]) {
+        let (out_payload, trailer) = out.split_at_mut(payload.len());
+        out_payload.copy_from_slice(payload);
+        trailer.copy_from_slice(&self.checksum(payload).to_le_bytes()[..self.trailer_bytes()]);
+    }
+
+    /// Returns the payload of a shard with checksum trailer, or `None` if
+    /// the checksum doesn't match.
+    pub(crate) fn verify(self, shard: &[u8]) -> Option<&[u8]> {
+        let payload_bytes = shard.len().checked_sub(self.trailer_bytes())?;
+        let (payload, trailer) = shard.split_at(payload_bytes);
+        let expected = &self.checksum(payload).to_le_bytes()[..self.trailer_bytes()];
+        (trailer == expected).then_some(payload)
+    }
+}
+
+// ======================================================================
+// CRC-32C (Castagnoli), slicing-by-8
+
+const CRC32C_POLY: u32 = 0x82F6_3B78;
+
+const CRC32C_TABLE: [[u32; 256]; 8] = {
+    let mut table = [[0; 256]; 8];
+
+    let mut i = 0u32;
+    while i < 256 {
+        let mut crc = i;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ CRC32C_POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[0][i as usize] = crc;
+        i += 1;
+    }
+
+    let mut i = 0;
+    while i < 256 {
+        let mut slice = 1;
+        while slice < 8 {
+            let prev = table[slice - 1][i];
+            table[slice][i] = (prev >> 8) ^ table[0][(prev & 0xFF) as usize];
+            slice += 1;
+        }
+        i += 1;
+    }
+
+    table
+};
+
+fn crc32c(data: &[u8]) -> u32 {
+    let t = &CRC32C_TABLE;
+    let mut crc = !0u32;
+
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let lo = crc ^ u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        let hi = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        crc = t[7][(lo & 0xFF) as usize]
+            ^ t[6][((lo >> 8) & 0xFF) as usize]
+            ^ t[5][((lo >> 16) & 0xFF) as usize]
+            ^ t[4][(lo >> 24) as usize]
+            ^ t[3][(hi & 0xFF) as usize]
+            ^ t[2][((hi >> 8) & 0xFF) as usize]
+            ^ t[1][((hi >> 16) & 0xFF) as usize]
+            ^ t[0][(hi >> 24) as usize];
+    }
+    for &byte in chunks.remainder() {
+        crc = (crc >> 8) ^ t[0][((crc ^ u32::from(byte)) & 0xFF) as usize];
+    }
+
+    !crc
+}
+
+// ======================================================================
+// xxHash64, seed 0
+
+const XXH_PRIME64_1: u64 = 0x9E37_79B1_85EB_CA87;
+const XXH_PRIME64_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const XXH_PRIME64_3: u64 = 0x1656_67B1_9E37_79F9;
+const XXH_PRIME64_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const XXH_PRIME64_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+fn xxh64_round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(XXH_PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(XXH_PRIME64_1)
+}
+
+fn xxh64_merge_round(acc: u64, val: u64) -> u64 {
+    (acc ^ xxh64_round(0, val))
+        .wrapping_mul(XXH_PRIME64_1)
+        .wrapping_add(XXH_PRIME64_4)
+}
+
+fn xxh64(data: &[u8]) -> u64 {
+    let read_u64 = |bytes: &[u8]| u64::from_le_bytes(bytes[..8].try_into().expect("8 bytes"));
+    let read_u32 = |bytes: &[u8]| u32::from_le_bytes(bytes[..4].try_into().expect("4 bytes"));
+
+    let mut stripes = data.chunks_exact(32);
+    let mut hash = if data.len() >= 32 {
+        let mut v = [
+            XXH_PRIME64_1.wrapping_add(XXH_PRIME64_2),
+            XXH_PRIME64_2,
+            0,
+            0u64.wrapping_sub(XXH_PRIME64_1),
+        ];
+        for stripe in &mut stripes {
+            for (lane, acc) in v.iter_mut().enumerate() {
+                *acc = xxh64_round(*acc, read_u64(&stripe[lane * 8..]));
+            }
+        }
+
+        let mut hash = v[0]
+            .rotate_left(1)
+            .wrapping_add(v[1].rotate_left(7))
+            .wrapping_add(v[2].rotate_left(12))
+            .wrapping_add(v[3].rotate_left(18));
+        for acc in v {
+            hash = xxh64_merge_round(hash, acc);
+        }
+        hash
+    } else {
+        XXH_PRIME64_5
+    };
+
+    hash = hash.wrapping_add(data.len() as u64);
+
+    let mut rest = stripes.remainder();
+    while rest.len() >= 8 {
+        hash ^= xxh64_round(0, read_u64(rest));
+        hash = hash
+            .rotate_left(27)
+            .wrapping_mul(XXH_PRIME64_1)
+            .wrapping_add(XXH_PRIME64_4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        hash ^= u64::from(read_u32(rest)).wrapping_mul(XXH_PRIME64_1);
+        hash = hash
+            .rotate_left(23)
+            .wrapping_mul(XXH_PRIME64_2)
+            .wrapping_add(XXH_PRIME64_3);
+        rest = &rest[4..];
+    }
+    for &byte in rest {
+        hash ^= u64::from(byte).wrapping_mul(XXH_PRIME64_5);
+        hash = hash.rotate_left(11).wrapping_mul(XXH_PRIME64_1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(XXH_PRIME64_2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(XXH_PRIME64_3);
+    hash ^= hash >> 32;
+    hash
+}
+
+// ======================================================================
+// Python API
+
+fn with_trailer<'py>(
+    py: Python<'py>,
+    algorithm: Algorithm,
+    payload: &[u8],
+) -> PyResult<&'py PyBytes> {
+    PyBytes::new_with(py, payload.len() + algorithm.trailer_bytes(), |out| {
+        algorithm.write_with_trailer(payload, out);
+        Ok(())
+    })
+}
+
+/// Splits shards into the payloads of those with a valid checksum and the
+/// sorted indices of those without.
+fn verify_all(
+    algorithm: Algorithm,
+    shards: &HashMap<usize, Shard>,
+) -> (HashMap<usize, &[u8]>, Vec<usize>) {
+    let mut valid = HashMap::new();
+    let mut rejected = Vec::new();
+    for (&idx, shard) in shards {
+        match algorithm.verify(shard.as_ref()) {
+            Some(payload) => {
+                valid.insert(idx, payload);
+            }
+            None => rejected.push(idx),
+        }
+    }
+    rejected.sort_unstable();
+    (valid, rejected)
+}
+
+/// Checksum of `data` with the given algorithm (`"crc32c"` or `"xxh64"`).
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+#[pyo3(signature = (data, algorithm=Algorithm::Crc32c))]
+pub(crate) fn checksum(data: Shard, algorithm: Algorithm) -> u64 {
+    algorithm.checksum(data.as_ref())
+}
+
+/// Like `encode`, but returns `(original, recovery)` where every shard has
+/// a checksum trailer appended, for use with `decode_checksummed`.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+#[pyo3(signature = (data, recovery_count, algorithm=Algorithm::Crc32c))]
+pub(crate) fn encode_checksummed(
+    py: Python<'_>,
+    data: Vec<Shard>,
+    recovery_count: usize,
+    algorithm: Algorithm,
+) -> PyResult<(Py<PyList>, Py<PyList>)> {
+    let mut encoder: DefaultRateEncoder<_> = crate::new_encoder(&data, recovery_count)?;
+    let encoder_result = crate::encode_shards(py, &mut encoder, &data)?;
+
+    let original = data
+        .iter()
+        .map(|s| with_trailer(py, algorithm, s.as_ref()))
+        .collect::<PyResult<Vec<_>>>()?;
+    let recovery = encoder_result
+        .recovery_iter()
+        .map(|s| with_trailer(py, algorithm, s))
+        .collect::<PyResult<Vec<_>>>()?;
+
+    Ok((
+        PyList::new(py, original).into(),
+        PyList::new(py, recovery).into(),
+    ))
+}
+
+/// Like `decode`, but takes shards as produced by `encode_checksummed`.
+/// Shards whose checksum doesn't match are dropped and treated as lost.
+///
+/// Returns `(restored, rejected)`, where `restored` is a dict of restored
+/// original shards (including any rejected ones) without trailers, and
+/// `rejected` is a dict with the sorted `"original"` and `"recovery"`
+/// indices of the dropped shards.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+#[pyo3(signature = (original_count, recovery_count, original, recovery, algorithm=Algorithm::Crc32c))]
+pub(crate) fn decode_checksummed(
+    py: Python<'_>,
+    original_count: usize,
+    recovery_count: usize,
+    original: HashMap<usize, Shard>,
+    recovery: HashMap<usize, Shard>,
+    algorithm: Algorithm,
+) -> PyResult<(Py<PyDict>, Py<PyDict>)> {
+    let ((original_valid, original_rejected), (recovery_valid, recovery_rejected)) = py
+        .allow_threads(|| {
+            (
+                verify_all(algorithm, &original),
+                verify_all(algorithm, &recovery),
+            )
+        });
+
+    let rejected = PyDict::new(py);
+    rejected.set_item("original", original_rejected)?;
+    rejected.set_item("recovery", recovery_rejected)?;
+
+    let restored = PyDict::new(py);
+
+    if original_valid.len() == original_count {
+        // Nothing to do, original data is complete.
+        return Ok((restored.into(), rejected.into()));
+    }
+
+    let mut decoder: DefaultRateDecoder<_> = crate::new_decoder(
+        original_count,
+        recovery_count,
+        &original_valid,
+        &recovery_valid,
+    )?;
+    let decoder_result = crate::decode_shards(py, &mut decoder, &original_valid, &recovery_valid)?;
+
+    for (idx, shard) in decoder_result.restored_original_iter() {
+        restored.set_item(idx, PyBytes::new(py, shard))?;
+    }
+    Ok((restored.into(), rejected.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32c_known_answers() {
+        // RFC 3720, appendix B.4.
+        let ascending: Vec<u8> = (0..32).collect();
+        for (data, expected) in [
+            (&b""[..], 0),
+            (b"123456789", 0xE306_9283),
+            (&[0; 32], 0x8A91_36AA),
+            (&[0xFF; 32], 0x62A8_AB43),
+            (&ascending, 0x46DD_794E),
+        ] {
+            assert_eq!(crc32c(data), expected, "{data:?}");
+        }
+    }
+
+    #[test]
+    fn xxh64_known_answers() {
+        for (data, expected) in [
+            (&b""[..], 0xEF46_DB37_51D8_E999),
+            (b"a", 0xD24E_C4F1_A98C_6E5B),
+            (b"abc", 0x44BC_2CF5_AD77_0999),
+            // Long enough for the 32-byte stripes.
+            (
+                b"Nobody inspects the spammish repetition",
+                0xFBCE_A83C_8A37_8BF1,
+            ),
+        ] {
+            assert_eq!(xxh64(data), expected, "{data:?}");
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! The `rsleopard` command-line tool, which protects and restores files with
+//! the same shard directories as `encode_file` and `decode_file`.
+
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::path::Path;
+use std::process::ExitCode;
+
+use crate::file::{self, FileError, Manifest, Report};
+
+const USAGE: &str = "\
+usage: rsleopard encode FILE DIR ORIGINAL_COUNT RECOVERY_COUNT SHARD_BYTES
+       rsleopard decode DIR FILE
+       rsleopard verify DIR
+       rsleopard repair DIR
+       rsleopard info DIR
+
+exit status:
+  0  success, no shards missing or damaged (after repairing them, for repair)
+  1  invalid arguments or an I/O error
+  2  shards missing or damaged, but the file can be restored
+  3  too many shards missing or damaged to restore the file";
+
+const EXIT_CORRUPTED: u8 = 2;
+const EXIT_UNRECOVERABLE: u8 = 3;
+
+/// Stripes listed in full before the rest is elided.
+const LISTED_STRIPES: usize = 8;
+
+enum Failure {
+    Usage(String),
+    File(FileError),
+}
+
+impl From<FileError> for Failure {
+    fn from(other: FileError) -> Self {
+        Self::File(other)
+    }
+}
+
+/// Runs `rsleopard` with the arguments of the process.
+#[must_use]
+pub fn main() -> ExitCode {
+    let args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    match run(&args) {
+        Ok(code) => code,
+        Err(Failure::Usage(message)) => {
+            eprintln!("rsleopard: {message}\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+        Err(Failure::File(
+            err @ FileError::Codec(reed_solomon_simd::Error::NotEnoughShards { .. }),
+        )) => {
+            eprintln!("rsleopard: {err}");
+            ExitCode::from(EXIT_UNRECOVERABLE)
+        }
+        Err(Failure::File(err)) => {
+            eprintln!("rsleopard: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[OsString]) -> Result<ExitCode, Failure> {
+    let Some((command, args)) = args.split_first() else {
+        return Err(Failure::Usage("missing command".to_owned()));
+    };
+    let path = |arg: &OsString| Path::new(arg).to_owned();
+
+    match (command.to_str(), args) {
+        (Some("encode"), [input, dir, original_count, recovery_count, shard_bytes]) => {
+            let manifest = file::encode(
+                &path(input),
+                &path(dir),
+                number("ORIGINAL_COUNT", original_count)?,
+                number("RECOVERY_COUNT", recovery_count)?,
+                number("SHARD_BYTES", shard_bytes)?,
+            )?;
+            print_manifest(&manifest);
+            Ok(ExitCode::SUCCESS)
+        }
+        (Some("decode"), [dir, output]) => {
+            let report = file::decode(&path(dir), &path(output))?;
+            print_report(&report);
+            Ok(status(&report))
+        }
+        (Some("verify"), [dir]) => {
+            let report = file::verify(&path(dir))?;
+            print_report(&report);
+            Ok(status(&report))
+        }
+        (Some("repair"), [dir]) => {
+            let report = file::repair(&path(dir))?;
+            print_report(&report);
+            if report.unrecoverable.is_empty() {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::from(EXIT_UNRECOVERABLE))
+            }
+        }
+        (Some("info"), [dir]) => {
+            print_manifest(&Manifest::read(&path(dir))?);
+            Ok(ExitCode::SUCCESS)
+        }
+        (Some("help" | "-h" | "--help"), []) => {
+            println!("{USAGE}");
+            Ok(ExitCode::SUCCESS)
+        }
+        (Some(command @ ("encode" | "decode" | "verify" | "repair" | "info")), _) => Err(
+            Failure::Usage(format!("wrong number of arguments for {command}")),
+        ),
+        _ => Err(Failure::Usage(format!(
+            "unknown command {}",
+            command.to_string_lossy()
+        ))),
+    }
+}
+
+fn number(name: &str, arg: &OsString) -> Result<usize, Failure> {
+    arg.to_str()
+        .and_then(|arg| arg.parse().ok())
+        .ok_or_else(|| {
+            Failure::Usage(format!(
+                "{name} must be a number, got {}",
+                arg.to_string_lossy()
+            ))
+        })
+}
+
+fn status(report: &Report) -> ExitCode {
+    if !report.unrecoverable.is_empty() {
+        ExitCode::from(EXIT_UNRECOVERABLE)
+    } else if !report.is_clean() {
+        ExitCode::from(EXIT_CORRUPTED)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn print_manifest(manifest: &Manifest) {
+    println!("name {}", manifest.name);
+    println!("length {}", manifest.length);
+    println!("original_count {}", manifest.original_count);
+    println!("recovery_count {}", manifest.recovery_count);
+    println!("shard_bytes {}", manifest.shard_bytes);
+    println!("stripes {}", manifest.stripes());
+}
+
+fn print_report(report: &Report) {
+    for index in &report.missing {
+        println!("missing: shard {index}");
+    }
+    for (index, stripes) in &report.damaged {
+        println!("damaged: shard {index} in {}", list_stripes(stripes));
+    }
+    if !report.unrecoverable.is_empty() {
+        println!("unrecoverable: {}", list_stripes(&report.unrecoverable));
+    }
+}
+
+fn list_stripes(stripes: &[u64]) -> String {
+    let mut list = format!("{} stripe(s):", stripes.len());
+    for stripe in stripes.iter().take(LISTED_STRIPES) {
+        write!(list, " {stripe}").expect("writing to a String can't fail");
+    }
+    if stripes.len() > LISTED_STRIPES {
+        list.push_str(" ...");
+    }
+    list
+}
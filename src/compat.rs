@@ -0,0 +1,32 @@
+//! Drop-in replacements for the APIs of other erasure coding libraries, to
+//! ease migrating to this one. Only the calling conventions are compatible:
+//! the shards themselves differ from the ones those libraries produce.
+
+use pyo3::prelude::*;
+
+mod pyeclib;
+mod zfec;
+
+/// Adds `reed_solomon_leopard.compat` with its submodules to `m`.
+pub(crate) fn register(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    let compat = PyModule::new(py, "reed_solomon_leopard.compat")?;
+
+    let zfec = PyModule::new(py, "reed_solomon_leopard.compat.zfec")?;
+    zfec.add_class::<zfec::Encoder>()?;
+    zfec.add_class::<zfec::Decoder>()?;
+    compat.add("zfec", zfec)?;
+
+    let pyeclib = PyModule::new(py, "reed_solomon_leopard.compat.pyeclib")?;
+    pyeclib.add_class::<pyeclib::ECDriver>()?;
+    compat.add("pyeclib", pyeclib)?;
+
+    m.add("compat", compat)?;
+
+    // Extension modules can't have real submodules, so make
+    // `import reed_solomon_leopard.compat.zfec` find them directly.
+    let modules = py.import("sys")?.getattr("modules")?;
+    for module in [compat, zfec, pyeclib] {
+        modules.set_item(module.name()?, module)?;
+    }
+    Ok(())
+}
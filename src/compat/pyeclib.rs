@@ -0,0 +1,176 @@
+//! The `ECDriver` class of `pyeclib`. Fragments carry the header of
+//! `pack_shard`, with fragment indices `0..k` for data fragments and
+//! `k..k + m` for parity fragments.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+use std::collections::HashMap;
+
+use crate::blob;
+use crate::buffer::Shard;
+use crate::error::Error;
+use crate::frame::{self, Header};
+use crate::repair;
+
+#[pyclass(module = "reed_solomon_leopard.compat.pyeclib")]
+pub(crate) struct ECDriver {
+    k: usize,
+    m: usize,
+}
+
+#[pymethods]
+impl ECDriver {
+    /// Further options of `pyeclib`, such as `ec_type`, are accepted and
+    /// ignored.
+    #[new]
+    #[pyo3(signature = (k, m, **_options))]
+    fn new(k: usize, m: usize, _options: Option<&PyDict>) -> PyResult<Self> {
+        if !crate::supports(k, m) {
+            return Err(Error(reed_solomon_simd::Error::UnsupportedShardCount {
+                original_count: k,
+                recovery_count: m,
+            })
+            .into());
+        }
+        Ok(Self { k, m })
+    }
+
+    /// Splits `data_bytes` into `k` data fragments and `m` parity fragments.
+    #[allow(clippy::needless_pass_by_value)]
+    fn encode(&self, py: Python<'_>, data_bytes: Shard) -> PyResult<Vec<Py<PyBytes>>> {
+        let data = data_bytes.as_ref();
+        let (fragments, _) = blob::encode_with(py, data, self.k, self.m, |idx, shard| {
+            let is_recovery = idx >= self.k;
+            let index = if is_recovery { idx - self.k } else { idx };
+            let header = Header::new(
+                self.k,
+                self.m,
+                index,
+                is_recovery,
+                shard.len(),
+                data.len() as u64,
+            )?;
+            frame::framed(py, &header, shard)
+        })?;
+        Ok(fragments.into_iter().map(Into::into).collect())
+    }
+
+    /// Restores the data from any `k` fragments. With `ranges`, a list of
+    /// inclusive `(begin, end)` byte ranges, returns a list of those ranges
+    /// of the data instead. Metadata is always checked.
+    #[allow(clippy::needless_pass_by_value)]
+    #[pyo3(signature = (fragment_payloads, ranges=None, force_metadata_checks=false))]
+    fn decode(
+        &self,
+        py: Python<'_>,
+        fragment_payloads: Vec<Shard>,
+        ranges: Option<Vec<(usize, usize)>>,
+        force_metadata_checks: bool,
+    ) -> PyResult<PyObject> {
+        let _ = force_metadata_checks;
+        self.check_stripe(&frame::parse_stripe(&fragment_payloads)?.0)?;
+
+        let data = frame::decode_framed(py, fragment_payloads)?;
+        let Some(ranges) = ranges else {
+            return Ok(data.into());
+        };
+
+        let data = data.as_bytes(py);
+        let ranges = ranges
+            .into_iter()
+            .map(|(begin, end)| {
+                data.get(begin..=end)
+                    .map(|range| PyBytes::new(py, range))
+                    .ok_or_else(|| {
+                        PyValueError::new_err(format!(
+                            "range {begin}-{end} is outside of the {} bytes of data",
+                            data.len()
+                        ))
+                    })
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(ranges.into_py(py))
+    }
+
+    /// Restores the fragments with the indices in `indexes_to_reconstruct`
+    /// from any `k` fragments.
+    #[allow(clippy::needless_pass_by_value)]
+    fn reconstruct(
+        &self,
+        py: Python<'_>,
+        fragment_payloads: Vec<Shard>,
+        indexes_to_reconstruct: Vec<usize>,
+    ) -> PyResult<Vec<Py<PyBytes>>> {
+        let (stripe, parsed) = frame::parse_stripe(&fragment_payloads)?;
+        self.check_stripe(&stripe)?;
+        if let Some(idx) = indexes_to_reconstruct
+            .iter()
+            .find(|&&idx| idx >= self.k + self.m)
+        {
+            return Err(PyValueError::new_err(format!(
+                "fragment index {idx} out of range for k + m = {}",
+                self.k + self.m
+            )));
+        }
+
+        let mut original = HashMap::new();
+        let mut recovery = HashMap::new();
+        for &(header, payload) in &parsed {
+            if header.is_recovery {
+                recovery.insert(header.index as usize, payload);
+            } else {
+                original.insert(header.index as usize, payload);
+            }
+        }
+
+        let with_recovery = indexes_to_reconstruct
+            .iter()
+            .any(|&idx| idx >= self.k && !recovery.contains_key(&(idx - self.k)));
+        let (restored_original, restored_recovery) =
+            repair::restore(py, self.k, self.m, &original, &recovery, with_recovery)?;
+        let restored_original: HashMap<usize, &PyBytes> = restored_original.into_iter().collect();
+        let restored_recovery: HashMap<usize, &PyBytes> = restored_recovery.into_iter().collect();
+
+        indexes_to_reconstruct
+            .iter()
+            .map(|&idx| {
+                let is_recovery = idx >= self.k;
+                let (index, given, restored) = if is_recovery {
+                    (idx - self.k, &recovery, &restored_recovery)
+                } else {
+                    (idx, &original, &restored_original)
+                };
+                let payload = given.get(&index).copied().unwrap_or_else(|| {
+                    restored
+                        .get(&index)
+                        .expect("missing fragment is restored")
+                        .as_bytes()
+                });
+                let header = Header {
+                    index: frame::to_u32("index", index)?,
+                    is_recovery,
+                    ..stripe
+                };
+                Ok(frame::framed(py, &header, payload)?.into())
+            })
+            .collect()
+    }
+}
+
+impl ECDriver {
+    fn check_stripe(&self, stripe: &Header) -> PyResult<()> {
+        if (
+            stripe.original_count as usize,
+            stripe.recovery_count as usize,
+        ) != (self.k, self.m)
+        {
+            return Err(PyValueError::new_err(format!(
+                "fragments are for k={}, m={}, not k={}, m={}",
+                stripe.original_count, stripe.recovery_count, self.k, self.m
+            )));
+        }
+        Ok(())
+    }
+}
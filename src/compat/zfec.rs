@@ -0,0 +1,150 @@
+//! The `Encoder` and `Decoder` classes of `zfec`. As there, `k` is the
+//! number of primary shares and `m` the total number of shares. Unlike
+//! `zfec`, share lengths must be even.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use std::collections::HashMap;
+
+use crate::buffer::Shard;
+use crate::error::Error;
+
+fn check_parameters(k: usize, m: usize) -> PyResult<()> {
+    if k == 0 || k > m {
+        return Err(PyValueError::new_err(format!(
+            "k must be at least 1 and at most m, got k={k}, m={m}"
+        )));
+    }
+    if m > k && !crate::supports(k, m - k) {
+        return Err(Error(reed_solomon_simd::Error::UnsupportedShardCount {
+            original_count: k,
+            recovery_count: m - k,
+        })
+        .into());
+    }
+    Ok(())
+}
+
+#[pyclass(module = "reed_solomon_leopard.compat.zfec")]
+pub(crate) struct Encoder {
+    k: usize,
+    m: usize,
+}
+
+#[pymethods]
+impl Encoder {
+    #[new]
+    fn new(k: usize, m: usize) -> PyResult<Self> {
+        check_parameters(k, m)?;
+        Ok(Self { k, m })
+    }
+
+    /// Returns the shares with the ids in `desired_share_ids`, all `m` by
+    /// default. Primary shares are the objects in `inshares` themselves.
+    #[allow(clippy::needless_pass_by_value)]
+    #[pyo3(signature = (inshares, desired_share_ids=None))]
+    fn encode(
+        &self,
+        py: Python<'_>,
+        inshares: Vec<&PyAny>,
+        desired_share_ids: Option<Vec<usize>>,
+    ) -> PyResult<Vec<PyObject>> {
+        if inshares.len() != self.k {
+            return Err(PyValueError::new_err(format!(
+                "expected {} input shares, got {}",
+                self.k,
+                inshares.len()
+            )));
+        }
+        let desired_share_ids = desired_share_ids.unwrap_or_else(|| (0..self.m).collect());
+        if let Some(id) = desired_share_ids.iter().find(|&&id| id >= self.m) {
+            return Err(PyValueError::new_err(format!(
+                "share id {id} out of range for m={}",
+                self.m
+            )));
+        }
+
+        let secondary = if desired_share_ids.iter().any(|&id| id >= self.k) {
+            let data = inshares
+                .iter()
+                .map(|share| share.extract())
+                .collect::<PyResult<Vec<Shard>>>()?;
+            Some(crate::encode(py, data, self.m - self.k)?.into_ref(py))
+        } else {
+            None
+        };
+
+        desired_share_ids
+            .iter()
+            .map(|&id| match secondary {
+                Some(secondary) if id >= self.k => secondary.get_item(id - self.k).map(Into::into),
+                _ => Ok(inshares[id].into()),
+            })
+            .collect()
+    }
+}
+
+#[pyclass(module = "reed_solomon_leopard.compat.zfec")]
+pub(crate) struct Decoder {
+    k: usize,
+    m: usize,
+}
+
+#[pymethods]
+impl Decoder {
+    #[new]
+    fn new(k: usize, m: usize) -> PyResult<Self> {
+        check_parameters(k, m)?;
+        Ok(Self { k, m })
+    }
+
+    /// Returns the `k` primary shares, given any `k` shares in `blocks` with
+    /// their share ids in `sharenums`.
+    #[allow(clippy::needless_pass_by_value)]
+    fn decode(
+        &self,
+        py: Python<'_>,
+        blocks: Vec<&PyAny>,
+        sharenums: Vec<usize>,
+    ) -> PyResult<Vec<PyObject>> {
+        if blocks.len() != sharenums.len() {
+            return Err(PyValueError::new_err(format!(
+                "got {} blocks but {} sharenums",
+                blocks.len(),
+                sharenums.len()
+            )));
+        }
+
+        let mut primary = HashMap::new();
+        let mut original = HashMap::new();
+        let mut recovery = HashMap::new();
+        for (&block, &sharenum) in blocks.iter().zip(&sharenums) {
+            if sharenum >= self.m {
+                return Err(PyValueError::new_err(format!(
+                    "sharenum {sharenum} out of range for m={}",
+                    self.m
+                )));
+            }
+            if sharenum < self.k {
+                primary.insert(sharenum, block);
+                original.insert(sharenum, block.extract::<Shard>()?);
+            } else {
+                recovery.insert(sharenum - self.k, block.extract::<Shard>()?);
+            }
+        }
+
+        let restored = crate::decode(py, self.k, self.m - self.k, original, recovery)?;
+        let restored = restored.as_ref(py);
+
+        (0..self.k)
+            .map(|idx| match primary.get(&idx) {
+                Some(&block) => Ok(block.into()),
+                None => Ok(restored
+                    .get_item(idx)?
+                    .expect("missing primary share is restored")
+                    .into()),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,349 @@
+//! Decoding which tolerates silently corrupted shards, using the surplus
+//! redundancy when more than `original_count` shards were received.
+//!
+//! Decoding from any `original_count` of the received shards (the basis) and
+//! encoding again gives a codeword. With up to `t = (received -
+//! original_count) / 2` corrupted shards, the basis is intact exactly when
+//! at most `t` of the other shards differ from that codeword, and those are
+//! then the corrupted ones. Otherwise the codeword differs from the received
+//! shards in more than `t` places.
+//!
+//! In that case the errors in one inconsistent symbol are located with a
+//! Reed-Solomon decoder for single symbols (see [`gf::Code`]), and decoding
+//! is tried again without the shards found.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+use reed_solomon_simd::{ReedSolomonDecoder, ReedSolomonEncoder};
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::buffer::Shard;
+use crate::error::Error;
+use crate::gf;
+
+/// A received shard and its position: original shards first, then
+/// recovery shards.
+type Received<'a> = (usize, &'a [u8]);
+
+/// Decodes from exactly `original_count` shards in `basis`, encodes again and
+/// calls `f` with a function returning the codeword at any position.
+fn with_codeword<R>(
+    encoder: &mut ReedSolomonEncoder,
+    decoder: &mut ReedSolomonDecoder,
+    original_count: usize,
+    basis: &[Received],
+    f: impl for<'c> FnOnce(&'c dyn Fn(usize) -> &'c [u8]) -> R,
+) -> Result<R, reed_solomon_simd::Error> {
+    let received: HashMap<usize, &[u8]> = basis.iter().copied().collect();
+
+    for &(position, shard) in basis {
+        if position < original_count {
+            decoder.add_original_shard(position, shard)?;
+        } else {
+            decoder.add_recovery_shard(position - original_count, shard)?;
+        }
+    }
+    let decoder_result = decoder.decode()?;
+
+    let original = |idx: usize| {
+        received
+            .get(&idx)
+            .copied()
+            .or_else(|| decoder_result.restored_original(idx))
+            .expect("original shard is either received or restored")
+    };
+
+    for idx in 0..original_count {
+        encoder.add_original_shard(original(idx))?;
+    }
+    let encoder_result = encoder.encode()?;
+
+    let codeword = |position: usize| {
+        if position < original_count {
+            original(position)
+        } else {
+            encoder_result
+                .recovery(position - original_count)
+                .expect("recovery index is in range")
+        }
+    };
+    Ok(f(&codeword))
+}
+
+/// Positions of the corrupted shards among `shards`, if there are at most
+/// `max_corrupted` of them.
+fn locate(
+    encoder: &mut ReedSolomonEncoder,
+    decoder: &mut ReedSolomonDecoder,
+    original_count: usize,
+    code: &gf::Code,
+    shards: &[Received],
+    max_corrupted: usize,
+) -> Result<Option<BTreeSet<usize>>, reed_solomon_simd::Error> {
+    let mut corrupted = BTreeSet::new();
+    loop {
+        let remaining: Vec<Received> = shards
+            .iter()
+            .copied()
+            .filter(|(position, _)| !corrupted.contains(position))
+            .collect();
+        let (basis, others) = remaining.split_at(original_count);
+
+        let differences = with_codeword(encoder, decoder, original_count, basis, |codeword| {
+            others
+                .iter()
+                .map(|&(position, shard)| {
+                    shard
+                        .iter()
+                        .zip(codeword(position))
+                        .map(|(a, b)| a ^ b)
+                        .collect::<Vec<u8>>()
+                })
+                .collect::<Vec<_>>()
+        })?;
+        let differing: Vec<usize> = others
+            .iter()
+            .zip(&differences)
+            .filter(|(_, difference)| difference.iter().any(|&b| b != 0))
+            .map(|(&(position, _), _)| position)
+            .collect();
+
+        if corrupted.len() + differing.len() <= max_corrupted {
+            corrupted.extend(differing);
+            return Ok(Some(corrupted));
+        }
+
+        // Some shard in the basis is corrupted. Locate the errors in the
+        // first symbol which is inconsistent.
+        let index = (0..basis[0].1.len() / 2)
+            .find(|&index| differences.iter().any(|d| gf::symbol(d, index) != 0))
+            .expect("a differing shard has a differing symbol");
+        let symbols: Vec<(usize, u16)> = remaining
+            .iter()
+            .map(|&(position, shard)| (position, gf::symbol(shard, index)))
+            .collect();
+        match code.locate_errors(&symbols) {
+            Some(located) if !located.is_empty() => corrupted.extend(located),
+            _ => return Ok(None),
+        }
+        if corrupted.len() > max_corrupted {
+            return Ok(None);
+        }
+    }
+}
+
+/// The shards in `original` and `recovery` by position, checked to be
+/// enough and of one size, with valid indices.
+fn received<'a>(
+    original_count: usize,
+    recovery_count: usize,
+    original: &'a HashMap<usize, Shard>,
+    recovery: &'a HashMap<usize, Shard>,
+) -> Result<Vec<Received<'a>>, Error> {
+    // Positions of original shards past `original_count` are recovery shards.
+    if let Some(&index) = original.keys().find(|&&index| index >= original_count) {
+        return Err(Error(reed_solomon_simd::Error::InvalidOriginalShardIndex {
+            original_count,
+            index,
+        }));
+    }
+
+    let mut shards: Vec<Received> = original
+        .iter()
+        .map(|(&idx, shard)| (idx, shard.as_ref()))
+        .chain(
+            recovery
+                .iter()
+                .map(|(&idx, shard)| (original_count + idx, shard.as_ref())),
+        )
+        .collect();
+    shards.sort_unstable_by_key(|&(position, _)| position);
+
+    if shards.len() < original_count {
+        return Err(Error(reed_solomon_simd::Error::NotEnoughShards {
+            original_count,
+            original_received_count: original.len(),
+            recovery_received_count: recovery.len(),
+        }));
+    }
+    let shard_bytes = shards.first().map_or(0, |(_, shard)| shard.len());
+    if let Some(&(_, shard)) = shards.iter().find(|(_, shard)| shard.len() != shard_bytes) {
+        return Err(Error(reed_solomon_simd::Error::DifferentShardSize {
+            shard_bytes,
+            got: shard.len(),
+        }));
+    }
+    if let Some(&(position, _)) = shards
+        .iter()
+        .find(|&&(position, _)| position >= original_count + recovery_count)
+    {
+        return Err(Error(reed_solomon_simd::Error::InvalidRecoveryShardIndex {
+            recovery_count,
+            index: position - original_count,
+        }));
+    }
+    Ok(shards)
+}
+
+/// Like `decode`, but if more than `original_count` shards are given, uses
+/// the surplus to locate shards which are silently corrupted and decodes
+/// without them. At most `(len(original) + len(recovery) - original_count)
+/// // 2` corrupted shards can be corrected.
+///
+/// Returns `(restored, rejected)` like `decode_checksummed`: `restored`
+/// holds every original shard that was missing or corrupted, and `rejected`
+/// is a dict with the sorted `"original"` and `"recovery"` indices of the
+/// shards found to be corrupted.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+pub(crate) fn decode_correcting(
+    py: Python<'_>,
+    original_count: usize,
+    recovery_count: usize,
+    original: HashMap<usize, Shard>,
+    recovery: HashMap<usize, Shard>,
+) -> PyResult<(Py<PyDict>, Py<PyDict>)> {
+    let shards = received(original_count, recovery_count, &original, &recovery)?;
+    let shard_bytes = shards.first().map_or(0, |(_, shard)| shard.len());
+
+    let mut encoder = ReedSolomonEncoder::new(original_count, recovery_count, shard_bytes)
+        .map_err(Error::from)?;
+    let mut decoder = ReedSolomonDecoder::new(original_count, recovery_count, shard_bytes)
+        .map_err(Error::from)?;
+
+    let positions: Vec<usize> = shards.iter().map(|&(position, _)| position).collect();
+    let code = gf::Code::new(original_count, recovery_count, &positions);
+    let max_corrupted = (shards.len() - original_count) / 2;
+
+    let outcome = py
+        .allow_threads(|| -> Result<_, reed_solomon_simd::Error> {
+            let Some(corrupted) = locate(
+                &mut encoder,
+                &mut decoder,
+                original_count,
+                &code,
+                &shards,
+                max_corrupted,
+            )?
+            else {
+                return Ok(None);
+            };
+
+            let trusted: Vec<Received> = shards
+                .iter()
+                .copied()
+                .filter(|(position, _)| !corrupted.contains(position))
+                .collect();
+            let restored = with_codeword(
+                &mut encoder,
+                &mut decoder,
+                original_count,
+                &trusted[..original_count],
+                |codeword| {
+                    (0..original_count)
+                        .filter(|idx| !original.contains_key(idx) || corrupted.contains(idx))
+                        .map(|idx| (idx, codeword(idx).to_vec()))
+                        .collect::<Vec<_>>()
+                },
+            )?;
+            Ok(Some((restored, corrupted)))
+        })
+        .map_err(Error)?;
+
+    let Some((restored_shards, corrupted)) = outcome else {
+        return Err(PyValueError::new_err(format!(
+            "could not locate the corrupted shards, more than {max_corrupted} may be corrupted"
+        )));
+    };
+
+    let restored = PyDict::new(py);
+    for (idx, shard) in restored_shards {
+        restored.set_item(idx, PyBytes::new(py, &shard))?;
+    }
+
+    let (original_rejected, recovery_rejected): (Vec<usize>, Vec<usize>) = corrupted
+        .iter()
+        .partition(|&&position| position < original_count);
+    let rejected = PyDict::new(py);
+    rejected.set_item("original", original_rejected)?;
+    rejected.set_item(
+        "recovery",
+        recovery_rejected
+            .iter()
+            .map(|position| position - original_count)
+            .collect::<Vec<_>>(),
+    )?;
+
+    Ok((restored.into(), rejected.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::gf::tests::{stripe, SHARD_COUNTS};
+    use crate::random::Random;
+
+    const SHARD_BYTES: usize = 130;
+
+    /// Replaces `corrupted` random shards of a stripe with random bytes and
+    /// returns what `locate` finds, and the corrupted positions.
+    fn locate_corrupted(
+        seed: u64,
+        (original_count, recovery_count): (usize, usize),
+        corrupted: usize,
+    ) -> (Option<BTreeSet<usize>>, BTreeSet<usize>) {
+        let mut random = Random(seed);
+        let mut shards = stripe(&mut random, original_count, recovery_count, SHARD_BYTES);
+        let expected: BTreeSet<usize> = random.shuffled(shards.len())[..corrupted]
+            .iter()
+            .copied()
+            .collect();
+        for &position in &expected {
+            shards[position] = random.bytes(SHARD_BYTES);
+        }
+
+        let received: Vec<Received> = shards.iter().map(Vec::as_slice).enumerate().collect();
+        let mut encoder =
+            ReedSolomonEncoder::new(original_count, recovery_count, SHARD_BYTES).unwrap();
+        let mut decoder =
+            ReedSolomonDecoder::new(original_count, recovery_count, SHARD_BYTES).unwrap();
+        let positions: Vec<usize> = (0..shards.len()).collect();
+        let code = gf::Code::new(original_count, recovery_count, &positions);
+        let located = locate(
+            &mut encoder,
+            &mut decoder,
+            original_count,
+            &code,
+            &received,
+            recovery_count / 2,
+        )
+        .unwrap();
+        (located, expected)
+    }
+
+    #[test]
+    fn corrects_up_to_half_the_redundancy() {
+        for counts in SHARD_COUNTS {
+            for corrupted in 0..=counts.1 / 2 {
+                for seed in 1..=3 {
+                    let (located, expected) = locate_corrupted(seed, counts, corrupted);
+                    assert_eq!(located, Some(expected), "{counts:?} {corrupted} {seed}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_more_than_half_the_redundancy() {
+        for counts in SHARD_COUNTS {
+            for seed in 1..=3 {
+                let (located, _) = locate_corrupted(seed, counts, counts.1 / 2 + 1);
+                assert_eq!(located, None, "{counts:?} {seed}");
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Probability of losing data, for choosing `recovery_count`. A stripe is
+//! lost when more than `recovery_count` of its shards fail.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use std::collections::HashMap;
+
+use crate::random::Random;
+
+/// Probability that more than `tolerated` of `shards` shards fail, each
+/// independently with probability `p`. Summed in log space, so tiny
+/// probabilities don't underflow.
+#[allow(clippy::cast_precision_loss)]
+fn loss_probability(shards: usize, tolerated: usize, p: f64) -> f64 {
+    if tolerated >= shards || p <= 0.0 {
+        return 0.0;
+    }
+    if p >= 1.0 {
+        return 1.0;
+    }
+
+    let ln_odds = (p / (1.0 - p)).ln();
+    // ln P(exactly k failures), starting at k = 0.
+    let mut ln_pmf = shards as f64 * (-p).ln_1p();
+    let mut max = f64::NEG_INFINITY;
+    let mut sum = 0.0;
+    for k in 1..=shards {
+        ln_pmf += ((shards - k + 1) as f64 / k as f64).ln() + ln_odds;
+        if k <= tolerated {
+            continue;
+        }
+        if ln_pmf > max {
+            sum = sum * (max - ln_pmf).exp() + 1.0;
+            max = ln_pmf;
+        } else {
+            sum += (ln_pmf - max).exp();
+        }
+    }
+    (max.exp() * sum).min(1.0)
+}
+
+fn check_probability(name: &str, p: f64) -> PyResult<()> {
+    if (0.0..=1.0).contains(&p) {
+        Ok(())
+    } else {
+        Err(PyValueError::new_err(format!(
+            "{name} must be between 0 and 1, got {p}"
+        )))
+    }
+}
+
+/// Returns the probability that a stripe of `original_count` original and
+/// `recovery_count` recovery shards is lost when each shard fails
+/// independently with probability `shard_failure_prob`.
+///
+/// `correlated_groups` adds failure domains, e.g. racks, as a list of
+/// `(failure_prob, shard_indices)`: each group fails as a whole with its
+/// probability, independently of the others. Shard indices count original
+/// shards first. Whether groups fail is then sampled in `trials` Monte-Carlo
+/// trials from `seed`, and the loss probability given the failed groups is
+/// computed exactly.
+#[allow(clippy::cast_precision_loss, clippy::needless_pass_by_value)]
+#[pyfunction]
+#[pyo3(signature = (
+    original_count,
+    recovery_count,
+    shard_failure_prob,
+    correlated_groups=None,
+    trials=100_000,
+    seed=0,
+))]
+pub(crate) fn durability(
+    py: Python<'_>,
+    original_count: usize,
+    recovery_count: usize,
+    shard_failure_prob: f64,
+    correlated_groups: Option<Vec<(f64, Vec<usize>)>>,
+    trials: usize,
+    seed: u64,
+) -> PyResult<f64> {
+    check_probability("shard_failure_prob", shard_failure_prob)?;
+    let shards = original_count + recovery_count;
+    let Some(groups) = correlated_groups.filter(|groups| !groups.is_empty()) else {
+        return Ok(
+            py.allow_threads(|| loss_probability(shards, recovery_count, shard_failure_prob))
+        );
+    };
+
+    for (failure_prob, indices) in &groups {
+        check_probability("group failure_prob", *failure_prob)?;
+        if let Some(index) = indices.iter().find(|&&index| index >= shards) {
+            return Err(PyValueError::new_err(format!(
+                "shard index {index} in correlated_groups out of range for {shards} shards"
+            )));
+        }
+    }
+    if trials == 0 {
+        return Err(PyValueError::new_err("trials must be at least 1"));
+    }
+
+    Ok(py.allow_threads(|| {
+        let mut random = Random(seed);
+        let mut failed = vec![0; shards];
+        // Loss probability by number of shards in failed groups.
+        let mut loss_given = HashMap::new();
+        let mut total = 0.0;
+
+        for trial in 1..=trials {
+            let mut group_failed = 0;
+            for (failure_prob, indices) in &groups {
+                if random.next_f64() < *failure_prob {
+                    for &index in indices {
+                        if failed[index] != trial {
+                            failed[index] = trial;
+                            group_failed += 1;
+                        }
+                    }
+                }
+            }
+            total += *loss_given.entry(group_failed).or_insert_with(|| {
+                recovery_count
+                    .checked_sub(group_failed)
+                    .map_or(1.0, |tolerated| {
+                        loss_probability(shards - group_failed, tolerated, shard_failure_prob)
+                    })
+            });
+        }
+        total / trials as f64
+    }))
+}
+
+/// Returns the smallest `recovery_count` which `supports` accepts with
+/// `original_count`, for which `durability` without correlated groups is at
+/// most `target_loss_prob`. Raises `ValueError` if there is none.
+#[pyfunction]
+pub(crate) fn min_recovery_for(
+    py: Python<'_>,
+    original_count: usize,
+    target_loss_prob: f64,
+    shard_failure_prob: f64,
+) -> PyResult<usize> {
+    check_probability("target_loss_prob", target_loss_prob)?;
+    check_probability("shard_failure_prob", shard_failure_prob)?;
+    if !crate::supports(original_count, 1) {
+        return Err(PyValueError::new_err(format!(
+            "original_count {original_count} is not supported"
+        )));
+    }
+
+    // Both `supports` and the loss probability are monotonic in the
+    // recovery count, so binary search for the largest supported and then
+    // for the smallest sufficient one.
+    let partition_point = |mut low: usize, mut high: usize, pred: &dyn Fn(usize) -> bool| {
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if pred(mid) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    };
+
+    py.allow_threads(|| {
+        let max_recovery_count = partition_point(1, 1 << 16, &|recovery_count| {
+            crate::supports(original_count, recovery_count)
+        }) - 1;
+        let too_risky = |recovery_count| {
+            loss_probability(
+                original_count + recovery_count,
+                recovery_count,
+                shard_failure_prob,
+            ) > target_loss_prob
+        };
+        if too_risky(max_recovery_count) {
+            return Err(PyValueError::new_err(format!(
+                "even the most recovery shards supported, {max_recovery_count}, exceed \
+                 target_loss_prob={target_loss_prob:e}"
+            )));
+        }
+        Ok(partition_point(1, max_recovery_count, &too_risky))
+    })
+}
@@ -0,0 +1,229 @@
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+create_exception!(
+    reed_solomon_leopard,
+    ReedSolomonError,
+    PyValueError,
+    "Base class of all errors reported by the encoder and decoder."
+);
+create_exception!(
+    reed_solomon_leopard,
+    DifferentShardSizeError,
+    ReedSolomonError,
+    "A shard is not the same size as the others. Has `shard_bytes` and `got`."
+);
+create_exception!(
+    reed_solomon_leopard,
+    DuplicateShardIndexError,
+    ReedSolomonError,
+    "A shard index was given twice. Has `index`."
+);
+create_exception!(
+    reed_solomon_leopard,
+    DuplicateOriginalShardIndexError,
+    DuplicateShardIndexError,
+    "An original shard index was given twice. Has `index`."
+);
+create_exception!(
+    reed_solomon_leopard,
+    DuplicateRecoveryShardIndexError,
+    DuplicateShardIndexError,
+    "A recovery shard index was given twice. Has `index`."
+);
+create_exception!(
+    reed_solomon_leopard,
+    InvalidShardIndexError,
+    ReedSolomonError,
+    "A shard index is out of range. Has `index`."
+);
+create_exception!(
+    reed_solomon_leopard,
+    InvalidOriginalShardIndexError,
+    InvalidShardIndexError,
+    "An original shard index is out of range. Has `original_count` and `index`."
+);
+create_exception!(
+    reed_solomon_leopard,
+    InvalidRecoveryShardIndexError,
+    InvalidShardIndexError,
+    "A recovery shard index is out of range. Has `recovery_count` and `index`."
+);
+create_exception!(
+    reed_solomon_leopard,
+    InvalidShardSizeError,
+    ReedSolomonError,
+    "Shard size is zero or odd. Has `shard_bytes`."
+);
+create_exception!(
+    reed_solomon_leopard,
+    NotEnoughShardsError,
+    ReedSolomonError,
+    "Too few shards to decode. Has `original_count`, `original_received_count` and `recovery_received_count`."
+);
+create_exception!(
+    reed_solomon_leopard,
+    TooFewOriginalShardsError,
+    ReedSolomonError,
+    "Too few original shards to encode. Has `original_count` and `original_received_count`."
+);
+create_exception!(
+    reed_solomon_leopard,
+    TooManyOriginalShardsError,
+    ReedSolomonError,
+    "Too many original shards to encode. Has `original_count`."
+);
+create_exception!(
+    reed_solomon_leopard,
+    UnsupportedShardCountError,
+    ReedSolomonError,
+    "Unsupported `original_count` / `recovery_count` combination. Has both as attributes."
+);
+
+pub(crate) struct Error(pub(crate) reed_solomon_simd::Error);
+
+impl From<reed_solomon_simd::Error> for Error {
+    fn from(other: reed_solomon_simd::Error) -> Self {
+        Self(other)
+    }
+}
+
+impl From<Error> for PyErr {
+    fn from(error: Error) -> Self {
+        use reed_solomon_simd::Error as E;
+
+        let message = error.0.to_string();
+        let (err, attributes) = match error.0 {
+            E::DifferentShardSize { shard_bytes, got } => (
+                DifferentShardSizeError::new_err(message),
+                vec![("shard_bytes", shard_bytes), ("got", got)],
+            ),
+            E::DuplicateOriginalShardIndex { index } => (
+                DuplicateOriginalShardIndexError::new_err(message),
+                vec![("index", index)],
+            ),
+            E::DuplicateRecoveryShardIndex { index } => (
+                DuplicateRecoveryShardIndexError::new_err(message),
+                vec![("index", index)],
+            ),
+            E::InvalidOriginalShardIndex {
+                original_count,
+                index,
+            } => (
+                InvalidOriginalShardIndexError::new_err(message),
+                vec![("original_count", original_count), ("index", index)],
+            ),
+            E::InvalidRecoveryShardIndex {
+                recovery_count,
+                index,
+            } => (
+                InvalidRecoveryShardIndexError::new_err(message),
+                vec![("recovery_count", recovery_count), ("index", index)],
+            ),
+            E::InvalidShardSize { shard_bytes } => (
+                InvalidShardSizeError::new_err(message),
+                vec![("shard_bytes", shard_bytes)],
+            ),
+            E::NotEnoughShards {
+                original_count,
+                original_received_count,
+                recovery_received_count,
+            } => (
+                NotEnoughShardsError::new_err(message),
+                vec![
+                    ("original_count", original_count),
+                    ("original_received_count", original_received_count),
+                    ("recovery_received_count", recovery_received_count),
+                ],
+            ),
+            E::TooFewOriginalShards {
+                original_count,
+                original_received_count,
+            } => (
+                TooFewOriginalShardsError::new_err(message),
+                vec![
+                    ("original_count", original_count),
+                    ("original_received_count", original_received_count),
+                ],
+            ),
+            E::TooManyOriginalShards { original_count } => (
+                TooManyOriginalShardsError::new_err(message),
+                vec![("original_count", original_count)],
+            ),
+            E::UnsupportedShardCount {
+                original_count,
+                recovery_count,
+            } => (
+                UnsupportedShardCountError::new_err(message),
+                vec![
+                    ("original_count", original_count),
+                    ("recovery_count", recovery_count),
+                ],
+            ),
+        };
+
+        Python::with_gil(|py| {
+            let value = err.value(py);
+            for (name, attribute) in attributes {
+                if let Err(setattr_err) = value.setattr(name, attribute) {
+                    return setattr_err;
+                }
+            }
+            err
+        })
+    }
+}
+
+pub(crate) fn register(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add("ReedSolomonError", py.get_type::<ReedSolomonError>())?;
+    m.add(
+        "DifferentShardSizeError",
+        py.get_type::<DifferentShardSizeError>(),
+    )?;
+    m.add(
+        "DuplicateShardIndexError",
+        py.get_type::<DuplicateShardIndexError>(),
+    )?;
+    m.add(
+        "DuplicateOriginalShardIndexError",
+        py.get_type::<DuplicateOriginalShardIndexError>(),
+    )?;
+    m.add(
+        "DuplicateRecoveryShardIndexError",
+        py.get_type::<DuplicateRecoveryShardIndexError>(),
+    )?;
+    m.add(
+        "InvalidShardIndexError",
+        py.get_type::<InvalidShardIndexError>(),
+    )?;
+    m.add(
+        "InvalidOriginalShardIndexError",
+        py.get_type::<InvalidOriginalShardIndexError>(),
+    )?;
+    m.add(
+        "InvalidRecoveryShardIndexError",
+        py.get_type::<InvalidRecoveryShardIndexError>(),
+    )?;
+    m.add(
+        "InvalidShardSizeError",
+        py.get_type::<InvalidShardSizeError>(),
+    )?;
+    m.add(
+        "NotEnoughShardsError",
+        py.get_type::<NotEnoughShardsError>(),
+    )?;
+    m.add(
+        "TooFewOriginalShardsError",
+        py.get_type::<TooFewOriginalShardsError>(),
+    )?;
+    m.add(
+        "TooManyOriginalShardsError",
+        py.get_type::<TooManyOriginalShardsError>(),
+    )?;
+    m.add(
+        "UnsupportedShardCountError",
+        py.get_type::<UnsupportedShardCountError>(),
+    )?;
+    Ok(())
+}
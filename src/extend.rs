@@ -0,0 +1,83 @@
+//! Recovery shards of a code which grows on demand, so that redundancy can be
+//! raised later without re-encoding the ones already stored.
+//!
+//! The shards come from the low rate code of `reed-solomon-simd`, in which
+//! recovery shard `i` is the same whatever the recovery count. `encode` and
+//! `ReedSolomonEncoder` choose between the low and the high rate code by the
+//! shard counts, and the recovery shards of the high rate code change with
+//! the recovery count, so no growing code can match theirs. Data which may
+//! need more recovery shards later must use this code from the start.
+
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+
+use reed_solomon_simd::rate::{LowRateDecoder, LowRateEncoder};
+
+use std::collections::HashMap;
+
+use crate::buffer::Shard;
+use crate::error::Error;
+
+/// Returns the `count` recovery shards from index `start` on of the growing
+/// code over `original`. Shards from any calls with the same `original`
+/// belong to the same code and can be decoded together by `decode_extended`.
+/// The work grows with `start + count`, which can be at most
+/// `65536 - original_count` rounded up to a power of two.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+pub(crate) fn encode_recovery_range(
+    py: Python<'_>,
+    original: Vec<Shard>,
+    start: usize,
+    count: usize,
+) -> Result<Py<PyList>, Error> {
+    if count == 0 {
+        return Ok(PyList::empty(py).into());
+    }
+    let recovery_count =
+        start
+            .checked_add(count)
+            .ok_or(reed_solomon_simd::Error::UnsupportedShardCount {
+                original_count: original.len(),
+                recovery_count: usize::MAX,
+            })?;
+
+    let mut encoder: LowRateEncoder<_> = crate::new_encoder(&original, recovery_count)?;
+    let encoder_result = crate::encode_shards(py, &mut encoder, &original)?;
+
+    let recovery_shards: Vec<&PyBytes> = encoder_result
+        .recovery_iter()
+        .skip(start)
+        .map(|s| PyBytes::new(py, s))
+        .collect();
+    Ok(PyList::new(py, recovery_shards).into())
+}
+
+/// Like `decode`, for recovery shards from `encode_recovery_range`, at any
+/// indices.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+pub(crate) fn decode_extended(
+    py: Python<'_>,
+    original_count: usize,
+    original: HashMap<usize, Shard>,
+    recovery: HashMap<usize, Shard>,
+) -> PyResult<Py<PyDict>> {
+    if original.len() == original_count {
+        return Ok(PyDict::new(py).into());
+    }
+
+    let recovery_count = recovery
+        .keys()
+        .max()
+        .map_or(0, |&idx| idx.saturating_add(1));
+    let mut decoder: LowRateDecoder<_> =
+        crate::new_decoder(original_count, recovery_count, &original, &recovery)?;
+    let decoder_result = crate::decode_shards(py, &mut decoder, &original, &recovery)?;
+
+    let py_dict = PyDict::new(py);
+    for (idx, shard) in decoder_result.restored_original_iter() {
+        py_dict.set_item(idx, PyBytes::new(py, shard))?;
+    }
+    Ok(py_dict.into())
+}
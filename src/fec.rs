@@ -0,0 +1,470 @@
+//! Forward error correction for packet streams, e.g. over UDP.
+//!
+//! The sender groups payloads into blocks of up to `original_count` payloads
+//! and sends each payload right away as an original packet. Once a block is
+//! full, or flushed early, it also sends `recovery_count` recovery packets.
+//! A block flushed early first has its last original packet sent again,
+//! marked with the actual number of payloads, so that the receiver learns
+//! the block's size even if all its recovery packets are lost.
+//! The original shard of a payload is its length as a little-endian `u16`,
+//! the payload and zero padding up to the block's even shard size, so the
+//! receiver restores lost payloads with their exact length.
+//!
+//! Packet header, all integers little-endian:
+//!
+//! | offset | size | field                                                  |
+//! | ------ | ---- | ------------------------------------------------------ |
+//! | 0      | 4    | block id, wrapping                                     |
+//! | 4      | 2    | index, original packets first                          |
+//! | 6      | 2    | `original_count` (*)                                   |
+//! | 8      | 2    | `recovery_count`                                       |
+//! | 10     | 2    | payload length (original) or shard size (recovery)     |
+//!
+//! (*) In recovery packets and the repeated last original packet of a block
+//! flushed early, the number of payloads in the block. In other original
+//! packets, the `original_count` of the sender, which is larger if the block
+//! was flushed early. Packets with an index of at least `original_count` are
+//! recovery packets.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use reed_solomon_simd::{ReedSolomonDecoder, ReedSolomonEncoder};
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::buffer::Shard;
+use crate::error::Error;
+
+const HEADER_BYTES: usize = 12;
+pub(crate) const LENGTH_BYTES: usize = 2;
+/// Largest payload, so that shard sizes fit in the header.
+pub(crate) const MAX_PAYLOAD_BYTES: usize = u16::MAX as usize - LENGTH_BYTES - 1;
+
+struct Header {
+    block: u32,
+    index: u16,
+    original_count: u16,
+    recovery_count: u16,
+    length: u16,
+}
+
+impl Header {
+    fn packet<'py>(&self, py: Python<'py>, body: &[u8]) -> &'py PyBytes {
+        PyBytes::new_with(py, HEADER_BYTES + body.len(), |packet| {
+            packet[0..4].copy_from_slice(&self.block.to_le_bytes());
+            packet[4..6].copy_from_slice(&self.index.to_le_bytes());
+            packet[6..8].copy_from_slice(&self.original_count.to_le_bytes());
+            packet[8..10].copy_from_slice(&self.recovery_count.to_le_bytes());
+            packet[10..12].copy_from_slice(&self.length.to_le_bytes());
+            packet[HEADER_BYTES..].copy_from_slice(body);
+            Ok(())
+        })
+        .expect("filling a new packet can't fail")
+    }
+
+    fn parse(packet: &[u8]) -> PyResult<(Self, &[u8])> {
+        if packet.len() < HEADER_BYTES {
+            return Err(PyValueError::new_err(format!(
+                "FEC packet too short: {} bytes, header alone is {HEADER_BYTES}",
+                packet.len()
+            )));
+        }
+        let u16_at = |offset: usize| {
+            u16::from_le_bytes(packet[offset..offset + 2].try_into().expect("2 bytes"))
+        };
+        let header = Self {
+            block: u32::from_le_bytes(packet[0..4].try_into().expect("4 bytes")),
+            index: u16_at(4),
+            original_count: u16_at(6),
+            recovery_count: u16_at(8),
+            length: u16_at(10),
+        };
+        let body = &packet[HEADER_BYTES..];
+
+        let is_recovery = header.index >= header.original_count;
+        if is_recovery && header.index - header.original_count >= header.recovery_count {
+            return Err(PyValueError::new_err(format!(
+                "FEC packet index {} out of range for {} + {} shards",
+                header.index, header.original_count, header.recovery_count
+            )));
+        }
+        if body.len() != usize::from(header.length) {
+            return Err(PyValueError::new_err(format!(
+                "FEC packet body is {} bytes, header says {}",
+                body.len(),
+                header.length
+            )));
+        }
+        Ok((header, body))
+    }
+}
+
+/// Writes the original shard of `payload` into `shard`.
+pub(crate) fn write_shard(payload: &[u8], shard: &mut [u8]) {
+    let length = u16::try_from(payload.len()).expect("payload length is checked");
+    shard[..LENGTH_BYTES].copy_from_slice(&length.to_le_bytes());
+    shard[LENGTH_BYTES..LENGTH_BYTES + payload.len()].copy_from_slice(payload);
+    shard[LENGTH_BYTES + payload.len()..].fill(0);
+}
+
+/// The payload in an original shard, if its length is valid.
+pub(crate) fn read_shard(shard: &[u8]) -> Option<&[u8]> {
+    let length = u16::from_le_bytes(shard.get(..LENGTH_BYTES)?.try_into().ok()?);
+    shard.get(LENGTH_BYTES..LENGTH_BYTES + usize::from(length))
+}
+
+/// Splits a stream of payloads into blocks and adds recovery packets.
+#[pyclass(module = "reed_solomon_leopard")]
+pub(crate) struct FecSender {
+    original_count: u16,
+    recovery_count: u16,
+    block: u32,
+    payloads: Vec<Vec<u8>>,
+    encoder: ReedSolomonEncoder,
+}
+
+#[pymethods]
+impl FecSender {
+    #[new]
+    fn new(original_count: usize, recovery_count: usize) -> PyResult<Self> {
+        // Packet indices must fit in the header.
+        if !crate::supports(original_count, recovery_count)
+            || original_count + recovery_count > usize::from(u16::MAX)
+        {
+            return Err(Error(reed_solomon_simd::Error::UnsupportedShardCount {
+                original_count,
+                recovery_count,
+            })
+            .into());
+        }
+
+        Ok(Self {
+            original_count: u16::try_from(original_count).expect("checked above"),
+            recovery_count: u16::try_from(recovery_count).expect("checked above"),
+            block: 0,
+            payloads: Vec::new(),
+            encoder: ReedSolomonEncoder::new(1, 1, LENGTH_BYTES).map_err(Error::from)?,
+        })
+    }
+
+    /// Id of the block the next payload goes into.
+    #[getter]
+    fn block(&self) -> u32 {
+        self.block
+    }
+
+    /// Returns the packets to send for `payload`: its original packet and,
+    /// if it fills the block, the block's recovery packets.
+    #[allow(clippy::needless_pass_by_value)]
+    fn send<'py>(&mut self, py: Python<'py>, payload: Shard) -> PyResult<Vec<&'py PyBytes>> {
+        let payload = payload.as_ref();
+        if payload.len() > MAX_PAYLOAD_BYTES {
+            return Err(PyValueError::new_err(format!(
+                "payload of {} bytes is larger than {MAX_PAYLOAD_BYTES}",
+                payload.len()
+            )));
+        }
+
+        let mut packets = vec![self.original_packet(py, self.payloads.len(), payload, false)];
+        self.payloads.push(payload.to_vec());
+        if self.payloads.len() == usize::from(self.original_count) {
+            packets.extend(self.flush(py)?);
+        }
+        Ok(packets)
+    }
+
+    /// Ends the current block early, e.g. at the end of a video frame, and
+    /// returns its recovery packets, after its last original packet again if
+    /// the block isn't full. Does nothing if the block is empty.
+    fn flush<'py>(&mut self, py: Python<'py>) -> PyResult<Vec<&'py PyBytes>> {
+        let Some(last) = self.payloads.last() else {
+            return Ok(Vec::new());
+        };
+        let mut packets = Vec::new();
+        if self.payloads.len() < usize::from(self.original_count) {
+            packets.push(self.original_packet(py, self.payloads.len() - 1, last, true));
+        }
+        let longest = self.payloads.iter().map(Vec::len).max().unwrap_or(0);
+        let shard_bytes = (LENGTH_BYTES + longest).next_multiple_of(2);
+        let original_count = self.payloads.len();
+
+        let payloads = &self.payloads;
+        let encoder = &mut self.encoder;
+        let recovery_count = usize::from(self.recovery_count);
+        let encoder_result = py
+            .allow_threads(|| {
+                encoder.reset(original_count, recovery_count, shard_bytes)?;
+                let mut shard = vec![0; shard_bytes];
+                for payload in payloads {
+                    write_shard(payload, &mut shard);
+                    encoder.add_original_shard(&shard)?;
+                }
+                encoder.encode()
+            })
+            .map_err(Error::from)?;
+
+        let original_count = u16::try_from(original_count).expect("below original_count");
+        packets.extend(encoder_result.recovery_iter().zip(0..).map(|(shard, idx)| {
+            Header {
+                block: self.block,
+                index: original_count + idx,
+                original_count,
+                recovery_count: self.recovery_count,
+                length: u16::try_from(shard_bytes).expect("shard size is checked"),
+            }
+            .packet(py, shard)
+        }));
+
+        self.payloads.clear();
+        self.block = self.block.wrapping_add(1);
+        Ok(packets)
+    }
+}
+
+impl FecSender {
+    /// The original packet of payload `index` of the current block. If it's
+    /// the `last` of a block flushed early, it carries the block's size.
+    fn original_packet<'py>(
+        &self,
+        py: Python<'py>,
+        index: usize,
+        payload: &[u8],
+        last: bool,
+    ) -> &'py PyBytes {
+        let index = u16::try_from(index).expect("below original_count");
+        Header {
+            block: self.block,
+            index,
+            original_count: if last { index + 1 } else { self.original_count },
+            recovery_count: self.recovery_count,
+            length: u16::try_from(payload.len()).expect("payload length is checked"),
+        }
+        .packet(py, payload)
+    }
+}
+
+/// Packets received for a block.
+struct Block {
+    /// Number of payloads, exact once a recovery packet or the repeated
+    /// last original packet arrived.
+    original_count: usize,
+    recovery_count: usize,
+    shard_bytes: Option<usize>,
+    /// Received and restored payloads, kept after their release for
+    /// decoding.
+    payloads: BTreeMap<usize, Vec<u8>>,
+    recovery: HashMap<usize, Vec<u8>>,
+    /// Payloads released or skipped so far.
+    released: usize,
+}
+
+impl Block {
+    fn new(header: &Header) -> Self {
+        Self {
+            original_count: header.original_count.into(),
+            recovery_count: header.recovery_count.into(),
+            shard_bytes: None,
+            payloads: BTreeMap::new(),
+            recovery: HashMap::new(),
+            released: 0,
+        }
+    }
+
+    fn is_recoverable(&self) -> bool {
+        let received = self.payloads.range(..self.original_count).count() + self.recovery.len();
+        self.shard_bytes.is_some() && received >= self.original_count
+    }
+
+    /// Restores the missing payloads.
+    fn decode(&mut self, decoder: &mut ReedSolomonDecoder) -> Result<(), reed_solomon_simd::Error> {
+        let shard_bytes = self.shard_bytes.expect("block is recoverable");
+        decoder.reset(self.original_count, self.recovery_count, shard_bytes)?;
+        let mut shard = vec![0; shard_bytes];
+        for (&idx, payload) in self.payloads.range(..self.original_count) {
+            if LENGTH_BYTES + payload.len() <= shard_bytes {
+                write_shard(payload, &mut shard);
+                decoder.add_original_shard(idx, &shard)?;
+            }
+        }
+        for (&idx, shard) in &self.recovery {
+            decoder.add_recovery_shard(idx, shard)?;
+        }
+        let decoder_result = decoder.decode()?;
+        for (idx, shard) in decoder_result.restored_original_iter() {
+            if let Some(payload) = read_shard(shard) {
+                self.payloads.insert(idx, payload.to_vec());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the payloads following those already released, up to the
+    /// first missing one, or all of them if `skip_missing` is set. Adds the
+    /// skipped ones to `lost`.
+    fn release(&mut self, skip_missing: bool, lost: &mut usize) -> Vec<Vec<u8>> {
+        let mut released = Vec::new();
+        while self.released < self.original_count {
+            match self.payloads.get(&self.released) {
+                Some(payload) => released.push(payload.clone()),
+                None if skip_missing => *lost += 1,
+                None => break,
+            }
+            self.released += 1;
+        }
+        released
+    }
+
+    fn is_done(&self) -> bool {
+        self.released >= self.original_count
+    }
+}
+
+/// Restores and orders the payloads from the packets of a `FecSender`.
+#[pyclass(module = "reed_solomon_leopard")]
+pub(crate) struct FecReceiver {
+    max_pending_blocks: u32,
+    /// Oldest block not yet done.
+    next_block: u32,
+    blocks: HashMap<u32, Block>,
+    decoder: ReedSolomonDecoder,
+    lost: usize,
+}
+
+#[pymethods]
+impl FecReceiver {
+    /// Once a packet arrives for a block `max_pending_blocks` after the
+    /// oldest one not yet done, the receiver gives up on the oldest one. The
+    /// stream starts at block 0, like that of a new `FecSender`, so the first
+    /// blocks count the same whichever of their packets arrives first.
+    #[new]
+    #[pyo3(signature = (max_pending_blocks=8))]
+    fn new(max_pending_blocks: u32) -> PyResult<Self> {
+        if max_pending_blocks == 0 {
+            return Err(PyValueError::new_err(
+                "max_pending_blocks must be at least 1",
+            ));
+        }
+        Ok(Self {
+            max_pending_blocks,
+            next_block: 0,
+            blocks: HashMap::new(),
+            decoder: ReedSolomonDecoder::new(1, 1, LENGTH_BYTES).map_err(Error::from)?,
+            lost: 0,
+        })
+    }
+
+    /// Number of payloads given up on so far. Blocks of which no packet
+    /// arrived at all aren't counted, as their size is unknown.
+    #[getter]
+    fn lost(&self) -> usize {
+        self.lost
+    }
+
+    /// Handles a received packet and returns the payloads which are now
+    /// released, in the order they were sent. Payloads are released as soon
+    /// as all before them are, restoring lost ones once enough packets of
+    /// their block arrived. Packets of blocks already done are ignored.
+    #[allow(clippy::needless_pass_by_value)]
+    fn receive<'py>(&mut self, py: Python<'py>, packet: Shard) -> PyResult<Vec<&'py PyBytes>> {
+        let (header, body) = Header::parse(packet.as_ref())?;
+        let ahead = header.block.wrapping_sub(self.next_block);
+        if ahead > u32::MAX / 2 {
+            return Ok(Vec::new());
+        }
+
+        let block = self
+            .blocks
+            .entry(header.block)
+            .or_insert_with(|| Block::new(&header));
+        let index = usize::from(header.index);
+        if index < usize::from(header.original_count) {
+            // Only the last original packet of a block flushed early has a
+            // smaller count, which is the block's size.
+            block.original_count = block.original_count.min(header.original_count.into());
+            block.payloads.entry(index).or_insert_with(|| body.to_vec());
+        } else if block
+            .shard_bytes
+            .is_none_or(|shard_bytes| shard_bytes == body.len())
+        {
+            block.original_count = header.original_count.into();
+            block.shard_bytes = Some(body.len());
+            block
+                .recovery
+                .entry(index - block.original_count)
+                .or_insert_with(|| body.to_vec());
+        }
+
+        let mut released = Vec::new();
+        let oldest_kept = header.block.wrapping_sub(self.max_pending_blocks - 1);
+        loop {
+            let behind = oldest_kept.wrapping_sub(self.next_block);
+            if behind == 0 || behind > u32::MAX / 2 {
+                break;
+            }
+            // Skip straight past block ids no packets arrived for.
+            if self
+                .blocks
+                .keys()
+                .all(|&block| block.wrapping_sub(self.next_block) >= behind)
+            {
+                self.next_block = oldest_kept;
+                break;
+            }
+            released.extend(self.give_up());
+        }
+        released.extend(self.release());
+        Ok(released
+            .iter()
+            .map(|payload| PyBytes::new(py, payload))
+            .collect())
+    }
+
+    /// Gives up on all blocks not yet done, e.g. at the end of the stream,
+    /// and returns their remaining received payloads in order.
+    fn flush<'py>(&mut self, py: Python<'py>) -> Vec<&'py PyBytes> {
+        let mut released = Vec::new();
+        while !self.blocks.is_empty() {
+            released.extend(self.give_up());
+            released.extend(self.release());
+        }
+        released
+            .iter()
+            .map(|payload| PyBytes::new(py, payload))
+            .collect()
+    }
+}
+
+impl FecReceiver {
+    /// Releases payloads in order, starting with the oldest block.
+    fn release(&mut self) -> Vec<Vec<u8>> {
+        let mut released = Vec::new();
+        while let Some(block) = self.blocks.get_mut(&self.next_block) {
+            released.extend(block.release(false, &mut self.lost));
+            if !block.is_done() && block.is_recoverable() {
+                // Decoding fails if the packets of the block disagree, e.g.
+                // after a corrupted one. Its missing payloads are then lost.
+                let _ = block.decode(&mut self.decoder);
+                released.extend(block.release(true, &mut self.lost));
+            }
+            if !block.is_done() {
+                break;
+            }
+            self.blocks.remove(&self.next_block);
+            self.next_block = self.next_block.wrapping_add(1);
+        }
+        released
+    }
+
+    /// Moves on from the oldest block not yet done, returning its remaining
+    /// received payloads.
+    fn give_up(&mut self) -> Vec<Vec<u8>> {
+        let next_block = self.next_block;
+        self.next_block = next_block.wrapping_add(1);
+        let Some(mut block) = self.blocks.remove(&next_block) else {
+            return Vec::new();
+        };
+        block.release(true, &mut self.lost)
+    }
+}
@@ -0,0 +1,702 @@
+//! Encoding of files too large to hold in memory. The file is read one
+//! stripe of `original_count * shard_bytes` bytes at a time, the last stripe
+//! zero-padded, so memory use doesn't depend on the file size.
+//!
+//! The output directory holds:
+//!
+//! - `manifest`: the stripe parameters, as `key value` lines after a
+//!   `reed-solomon-leopard 1` header line.
+//! - `00000.shard`, `00001.shard`, ...: one file per shard index, original
+//!   shards first, holding that shard of every stripe in turn. Each shard is
+//!   followed by a CRC32C trailer, so that damaged shards are detected and
+//!   treated as lost.
+
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use reed_solomon_simd::engine::GF_ORDER;
+use reed_solomon_simd::{DecoderResult, ReedSolomonDecoder, ReedSolomonEncoder};
+
+use std::collections::{BTreeMap, VecDeque};
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::checksum::Algorithm;
+use crate::error::Error;
+
+pub(crate) const MANIFEST: &str = "manifest";
+const MANIFEST_HEADER: &str = "reed-solomon-leopard 1";
+const CHECKSUM: Algorithm = Algorithm::Crc32c;
+
+pub(crate) enum FileError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Manifest(String),
+    /// Stripe parameters whose buffers don't fit in memory.
+    Size(String),
+    Codec(reed_solomon_simd::Error),
+}
+
+impl FileError {
+    pub(crate) fn io(path: &Path) -> impl FnOnce(io::Error) -> Self + '_ {
+        move |source| Self::Io {
+            path: path.to_owned(),
+            source,
+        }
+    }
+}
+
+impl From<reed_solomon_simd::Error> for FileError {
+    fn from(other: reed_solomon_simd::Error) -> Self {
+        Self::Codec(other)
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            Self::Manifest(message) => write!(f, "invalid manifest: {message}"),
+            Self::Size(message) => f.write_str(message),
+            Self::Codec(err) => err.fmt(f),
+        }
+    }
+}
+
+impl From<FileError> for PyErr {
+    fn from(error: FileError) -> Self {
+        match error {
+            // `OSError(errno, strerror, filename)` picks the matching
+            // subclass, e.g. `FileNotFoundError`.
+            FileError::Io { path, source } => match source.raw_os_error() {
+                Some(errno) => PyOSError::new_err((errno, source.to_string(), path)),
+                None => PyOSError::new_err(format!("{}: {source}", path.display())),
+            },
+            FileError::Manifest(_) | FileError::Size(_) => PyValueError::new_err(error.to_string()),
+            FileError::Codec(err) => Error(err).into(),
+        }
+    }
+}
+
+/// Stripe parameters of an encoded file.
+pub(crate) struct Manifest {
+    pub(crate) original_count: usize,
+    pub(crate) recovery_count: usize,
+    pub(crate) shard_bytes: usize,
+    pub(crate) length: u64,
+    /// File name of the encoded file, for information only.
+    pub(crate) name: String,
+}
+
+impl Manifest {
+    pub(crate) fn stripe_bytes(&self) -> u64 {
+        (self.original_count * self.shard_bytes) as u64
+    }
+
+    pub(crate) fn stripes(&self) -> u64 {
+        self.length.div_ceil(self.stripe_bytes())
+    }
+
+    pub(crate) fn shard_count(&self) -> usize {
+        self.original_count + self.recovery_count
+    }
+
+    pub(crate) fn read(dir: &Path) -> Result<Self, FileError> {
+        let path = dir.join(MANIFEST);
+        let text = fs::read_to_string(&path).map_err(FileError::io(&path))?;
+
+        let mut lines = text.lines();
+        if lines.next() != Some(MANIFEST_HEADER) {
+            return Err(FileError::Manifest(format!(
+                "{} doesn't start with '{MANIFEST_HEADER}'",
+                path.display()
+            )));
+        }
+        let fields: BTreeMap<&str, &str> = lines.filter_map(|line| line.split_once(' ')).collect();
+        let field = |key: &str| {
+            fields
+                .get(key)
+                .copied()
+                .ok_or_else(|| FileError::Manifest(format!("missing '{key}'")))
+        };
+        let number = |key: &str| {
+            field(key)?
+                .parse::<u64>()
+                .map_err(|_| FileError::Manifest(format!("'{key}' is not a number")))
+        };
+        let count = |key: &str| {
+            usize::try_from(number(key)?)
+                .map_err(|_| FileError::Manifest(format!("'{key}' is too large")))
+        };
+
+        let manifest = Self {
+            original_count: count("original_count")?,
+            recovery_count: count("recovery_count")?,
+            shard_bytes: count("shard_bytes")?,
+            length: number("length")?,
+            name: field("name")?.to_owned(),
+        };
+        buffer_sizes(manifest.original_count, manifest.shard_bytes).map_err(FileError::Manifest)?;
+        // Also validates the counts and the shard size.
+        ReedSolomonDecoder::new(
+            manifest.original_count,
+            manifest.recovery_count,
+            manifest.shard_bytes,
+        )?;
+        Ok(manifest)
+    }
+
+    fn write(&self, dir: &Path) -> Result<(), FileError> {
+        let path = dir.join(MANIFEST);
+        let text = format!(
+            "{MANIFEST_HEADER}\n\
+             original_count {}\n\
+             recovery_count {}\n\
+             shard_bytes {}\n\
+             length {}\n\
+             name {}\n",
+            self.original_count,
+            self.recovery_count,
+            self.shard_bytes,
+            self.length,
+            self.name.replace('\n', " "),
+        );
+        fs::write(&path, text).map_err(FileError::io(&path))
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("original_count", self.original_count)?;
+        dict.set_item("recovery_count", self.recovery_count)?;
+        dict.set_item("shard_bytes", self.shard_bytes)?;
+        dict.set_item("length", self.length)?;
+        dict.set_item("stripes", self.stripes())?;
+        dict.set_item("name", &self.name)?;
+        Ok(dict)
+    }
+}
+
+pub(crate) fn shard_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("{index:05}.shard"))
+}
+
+/// Like `Read::read_exact`, but returns the number of bytes read if the end
+/// of the input is reached first.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+/// At most this many shard files are open at a time, well below the common
+/// limit of 1024 open files per process. With more shards, files are closed
+/// and reopened as needed, at the cost of a few system calls per shard.
+pub(crate) const MAX_OPEN_FILES: usize = 256;
+
+/// Open files by shard index, at most `MAX_OPEN_FILES` of them.
+pub(crate) struct FilePool<F> {
+    files: Vec<Option<F>>,
+    /// Indices of the open files, in the order they were opened.
+    opened: VecDeque<usize>,
+}
+
+impl<F> FilePool<F> {
+    pub(crate) fn new(count: usize) -> Self {
+        Self {
+            files: (0..count).map(|_| None).collect(),
+            opened: VecDeque::new(),
+        }
+    }
+
+    /// File `index`, opened with `open` unless it's open already. To make
+    /// room, the file opened first is passed to `close`. Shards are accessed
+    /// in turn, so that one is needed last.
+    pub(crate) fn get<E>(
+        &mut self,
+        index: usize,
+        open: impl FnOnce() -> Result<F, E>,
+        close: impl FnOnce(usize, F) -> Result<(), E>,
+    ) -> Result<&mut F, E> {
+        if self.files[index].is_none() {
+            if self.opened.len() == MAX_OPEN_FILES {
+                let oldest = self.opened.pop_front().expect("files are open");
+                close(oldest, self.files[oldest].take().expect("file is open"))?;
+            }
+            self.files[index] = Some(open()?);
+            self.opened.push_back(index);
+        }
+        Ok(self.files[index].as_mut().expect("file is open"))
+    }
+
+    /// Passes all open files to `close`.
+    pub(crate) fn close_all<E>(
+        &mut self,
+        mut close: impl FnMut(usize, F) -> Result<(), E>,
+    ) -> Result<(), E> {
+        while let Some(index) = self.opened.pop_front() {
+            close(index, self.files[index].take().expect("file is open"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Opens `path` for reading at `position`.
+pub(crate) fn open_at(path: &Path, position: u64) -> Result<BufReader<File>, FileError> {
+    let mut file = File::open(path).map_err(FileError::io(path))?;
+    file.seek(SeekFrom::Start(position))
+        .map_err(FileError::io(path))?;
+    Ok(BufReader::new(file))
+}
+
+/// Bytes of a stripe and of a shard with its checksum, the buffers needed
+/// to encode or decode, or why they are too large. The work buffers of the
+/// codec hold up to `GF_ORDER` shards rounded up to 64 bytes, so they must
+/// fit too.
+fn buffer_sizes(original_count: usize, shard_bytes: usize) -> Result<(usize, usize), String> {
+    let fits = |&bytes: &usize| bytes <= isize::MAX.unsigned_abs();
+    let work_fits = shard_bytes
+        .checked_next_multiple_of(64)
+        .and_then(|bytes| bytes.checked_mul(GF_ORDER))
+        .is_some_and(|bytes| fits(&bytes));
+    original_count
+        .checked_mul(shard_bytes)
+        .filter(|_| work_fits)
+        .filter(fits)
+        .zip(
+            shard_bytes
+                .checked_add(CHECKSUM.trailer_bytes())
+                .filter(fits),
+        )
+        .ok_or_else(|| format!("{original_count} shards of {shard_bytes} bytes are too large"))
+}
+
+/// Encodes the file at `path` into a directory of shard files and a
+/// manifest. The manifest is written last, once all shards are complete.
+pub(crate) fn encode(
+    path: &Path,
+    out_dir: &Path,
+    original_count: usize,
+    recovery_count: usize,
+    shard_bytes: usize,
+) -> Result<Manifest, FileError> {
+    let (stripe_bytes, framed_bytes) =
+        buffer_sizes(original_count, shard_bytes).map_err(FileError::Size)?;
+    let mut encoder = ReedSolomonEncoder::new(original_count, recovery_count, shard_bytes)?;
+
+    let mut input = File::open(path).map_err(FileError::io(path))?;
+    fs::create_dir_all(out_dir).map_err(FileError::io(out_dir))?;
+    let paths: Vec<PathBuf> = (0..original_count + recovery_count)
+        .map(|index| shard_path(out_dir, index))
+        .collect();
+    let flush = |index: usize, mut output: BufWriter<File>| {
+        output.flush().map_err(FileError::io(&paths[index]))
+    };
+    let mut outputs = FilePool::new(paths.len());
+    // Create all files up front, so that even an empty file has shard files.
+    for (index, path) in paths.iter().enumerate() {
+        outputs.get(
+            index,
+            || {
+                File::create(path)
+                    .map(BufWriter::new)
+                    .map_err(FileError::io(path))
+            },
+            flush,
+        )?;
+    }
+
+    let mut stripe = vec![0; stripe_bytes];
+    let mut framed = vec![0; framed_bytes];
+    let mut write_shard = |index: usize, shard: &[u8]| {
+        let path = &paths[index];
+        let output = outputs.get(
+            index,
+            || {
+                OpenOptions::new()
+                    .append(true)
+                    .open(path)
+                    .map(BufWriter::new)
+                    .map_err(FileError::io(path))
+            },
+            flush,
+        )?;
+        CHECKSUM.write_with_trailer(shard, &mut framed);
+        output.write_all(&framed).map_err(FileError::io(path))
+    };
+
+    let mut length = 0;
+    loop {
+        let read = read_full(&mut input, &mut stripe).map_err(FileError::io(path))?;
+        if read == 0 {
+            break;
+        }
+        stripe[read..].fill(0);
+        length += read as u64;
+
+        for (index, shard) in stripe.chunks(shard_bytes).enumerate() {
+            encoder.add_original_shard(shard)?;
+            write_shard(index, shard)?;
+        }
+        let encoder_result = encoder.encode()?;
+        for (idx, shard) in encoder_result.recovery_iter().enumerate() {
+            write_shard(original_count + idx, shard)?;
+        }
+
+        if read < stripe.len() {
+            break;
+        }
+    }
+
+    outputs.close_all(flush)?;
+
+    let manifest = Manifest {
+        original_count,
+        recovery_count,
+        shard_bytes,
+        length,
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+    manifest.write(out_dir)?;
+    Ok(manifest)
+}
+
+/// Shards found missing or damaged while reading a directory of shards.
+#[derive(Default)]
+pub(crate) struct Report {
+    /// Shard indices without a shard file.
+    pub(crate) missing: Vec<usize>,
+    /// Stripes with a damaged or truncated shard, by shard index.
+    pub(crate) damaged: BTreeMap<usize, Vec<u64>>,
+    /// Stripes with fewer than `original_count` intact shards.
+    pub(crate) unrecoverable: Vec<u64>,
+}
+
+impl Report {
+    pub(crate) fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.damaged.is_empty()
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("missing", &self.missing)?;
+        dict.set_item(
+            "damaged",
+            self.damaged.clone().into_iter().collect::<Vec<_>>(),
+        )?;
+        Ok(dict)
+    }
+}
+
+/// Reads the shard files of a directory one stripe at a time.
+struct StripeReader {
+    original_count: usize,
+    files: FilePool<BufReader<File>>,
+    /// Whether the shard file exists.
+    present: Vec<bool>,
+    /// Whether the file ended early. Its later shards are all damaged.
+    truncated: Vec<bool>,
+    paths: Vec<PathBuf>,
+    buffers: Vec<Vec<u8>>,
+    valid: Vec<bool>,
+    stripe: u64,
+    report: Report,
+}
+
+impl StripeReader {
+    fn open(dir: &Path, manifest: &Manifest) -> Result<Self, FileError> {
+        let shard_count = manifest.shard_count();
+        let mut report = Report::default();
+        let mut files = FilePool::new(shard_count);
+        let mut present = Vec::new();
+        let mut paths = Vec::new();
+        for index in 0..shard_count {
+            let path = shard_path(dir, index);
+            match File::open(&path) {
+                Ok(file) => {
+                    files.get(
+                        index,
+                        || Ok::<_, FileError>(BufReader::new(file)),
+                        |_, _| Ok(()),
+                    )?;
+                    present.push(true);
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    report.missing.push(index);
+                    present.push(false);
+                }
+                Err(err) => return Err(FileError::io(&path)(err)),
+            }
+            paths.push(path);
+        }
+
+        let framed_bytes = manifest.shard_bytes + CHECKSUM.trailer_bytes();
+        Ok(Self {
+            original_count: manifest.original_count,
+            files,
+            present,
+            buffers: vec![vec![0; framed_bytes]; shard_count],
+            truncated: vec![false; shard_count],
+            valid: vec![false; shard_count],
+            paths,
+            stripe: 0,
+            report,
+        })
+    }
+
+    /// Reads the next stripe. Afterwards `shard(index)` returns the shards
+    /// which are intact.
+    fn read_stripe(&mut self) -> Result<(), FileError> {
+        for (index, path) in self.paths.iter().enumerate() {
+            if !self.present[index] {
+                self.valid[index] = false;
+                continue;
+            }
+            let buffer = &mut self.buffers[index];
+            if !self.truncated[index] {
+                let position = self.stripe * buffer.len() as u64;
+                let file = self
+                    .files
+                    .get(index, || open_at(path, position), |_, _| Ok(()))?;
+                let read = read_full(file, buffer).map_err(FileError::io(path))?;
+                self.truncated[index] = read < buffer.len();
+            }
+            self.valid[index] = !self.truncated[index] && CHECKSUM.verify(buffer).is_some();
+            if !self.valid[index] {
+                self.report
+                    .damaged
+                    .entry(index)
+                    .or_default()
+                    .push(self.stripe);
+            }
+        }
+
+        if self.valid.iter().filter(|&&valid| valid).count() < self.original_count {
+            self.report.unrecoverable.push(self.stripe);
+        }
+        self.stripe += 1;
+        Ok(())
+    }
+
+    /// Shard `index` of the current stripe, if it's intact.
+    fn shard(&self, index: usize) -> Option<&[u8]> {
+        self.valid[index].then(|| CHECKSUM.verify(&self.buffers[index]).expect("verified"))
+    }
+
+    /// Restores the lost original shards of the current stripe, if any.
+    fn decode<'a>(
+        &self,
+        decoder: &'a mut ReedSolomonDecoder,
+    ) -> Result<Option<DecoderResult<'a>>, FileError> {
+        if (0..self.original_count).all(|idx| self.valid[idx]) {
+            return Ok(None);
+        }
+        for index in 0..self.paths.len() {
+            if let Some(shard) = self.shard(index) {
+                if index < self.original_count {
+                    decoder.add_original_shard(index, shard)?;
+                } else {
+                    decoder.add_recovery_shard(index - self.original_count, shard)?;
+                }
+            }
+        }
+        Ok(Some(decoder.decode()?))
+    }
+}
+
+/// Restores the file encoded in `dir` to `out_path` from whichever shards are
+/// intact, and reports the missing and damaged ones. The file is written
+/// next to `out_path` first and renamed into place once every stripe is
+/// restored, so a failure leaves no partial output behind.
+pub(crate) fn decode(dir: &Path, out_path: &Path) -> Result<Report, FileError> {
+    let mut name = OsString::from(".");
+    name.push(out_path.file_name().unwrap_or_default());
+    name.push(".partial");
+    let partial = out_path.with_file_name(name);
+
+    match restore(dir, &partial) {
+        Ok(report) => {
+            fs::rename(&partial, out_path).map_err(FileError::io(out_path))?;
+            Ok(report)
+        }
+        Err(err) => {
+            // The error from decoding matters more than one from cleaning up.
+            let _ = fs::remove_file(&partial);
+            Err(err)
+        }
+    }
+}
+
+/// Like `decode`, writing to `out_path` directly.
+fn restore(dir: &Path, out_path: &Path) -> Result<Report, FileError> {
+    let manifest = Manifest::read(dir)?;
+    let original_count = manifest.original_count;
+    let mut decoder = ReedSolomonDecoder::new(
+        original_count,
+        manifest.recovery_count,
+        manifest.shard_bytes,
+    )?;
+    let mut reader = StripeReader::open(dir, &manifest)?;
+
+    let output = File::create(out_path).map_err(FileError::io(out_path))?;
+    let mut output = BufWriter::new(output);
+    let mut remaining = manifest.length;
+
+    for _ in 0..manifest.stripes() {
+        reader.read_stripe()?;
+
+        let decoder_result = reader.decode(&mut decoder)?;
+
+        for idx in 0..original_count {
+            let shard = reader
+                .shard(idx)
+                .or_else(|| decoder_result.as_ref()?.restored_original(idx))
+                .expect("original shard is either intact or restored");
+            let take = usize::try_from(remaining).map_or(shard.len(), |r| r.min(shard.len()));
+            output
+                .write_all(&shard[..take])
+                .map_err(FileError::io(out_path))?;
+            remaining -= take as u64;
+        }
+    }
+
+    output.flush().map_err(FileError::io(out_path))?;
+    Ok(reader.report)
+}
+
+/// Checks the shards of the file encoded in `dir` without decoding.
+pub(crate) fn verify(dir: &Path) -> Result<Report, FileError> {
+    let manifest = Manifest::read(dir)?;
+    let mut reader = StripeReader::open(dir, &manifest)?;
+    for _ in 0..manifest.stripes() {
+        reader.read_stripe()?;
+    }
+    Ok(reader.report)
+}
+
+/// Rewrites the missing and damaged shards of the file encoded in `dir` from
+/// the intact ones, stripe by stripe, and reports what was lost. Stripes
+/// without enough intact shards are left as they are.
+pub(crate) fn repair(dir: &Path) -> Result<Report, FileError> {
+    let manifest = Manifest::read(dir)?;
+    let original_count = manifest.original_count;
+    let mut decoder = ReedSolomonDecoder::new(
+        original_count,
+        manifest.recovery_count,
+        manifest.shard_bytes,
+    )?;
+    let mut encoder = ReedSolomonEncoder::new(
+        original_count,
+        manifest.recovery_count,
+        manifest.shard_bytes,
+    )?;
+    let mut reader = StripeReader::open(dir, &manifest)?;
+
+    let mut framed = vec![0; manifest.shard_bytes + CHECKSUM.trailer_bytes()];
+    let mut writers = FilePool::new(manifest.shard_count());
+
+    for stripe in 0..manifest.stripes() {
+        reader.read_stripe()?;
+        let lost: Vec<usize> = (0..manifest.shard_count())
+            .filter(|&index| reader.shard(index).is_none())
+            .collect();
+        if lost.is_empty() || reader.report.unrecoverable.last() == Some(&stripe) {
+            continue;
+        }
+
+        let decoder_result = reader.decode(&mut decoder)?;
+        let original = |idx| {
+            reader
+                .shard(idx)
+                .or_else(|| decoder_result.as_ref()?.restored_original(idx))
+                .expect("original shard is either intact or restored")
+        };
+        let encoder_result = if lost.iter().any(|&index| index >= original_count) {
+            for idx in 0..original_count {
+                encoder.add_original_shard(original(idx))?;
+            }
+            Some(encoder.encode()?)
+        } else {
+            None
+        };
+
+        for index in lost {
+            let shard = match &encoder_result {
+                Some(encoder_result) if index >= original_count => encoder_result
+                    .recovery(index - original_count)
+                    .expect("recovery shard is encoded"),
+                _ => original(index),
+            };
+            CHECKSUM.write_with_trailer(shard, &mut framed);
+
+            let path = shard_path(dir, index);
+            let writer = writers.get(
+                index,
+                || {
+                    OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(false)
+                        .open(&path)
+                        .map_err(FileError::io(&path))
+                },
+                |_, _| Ok(()),
+            )?;
+            writer
+                .seek(SeekFrom::Start(stripe * framed.len() as u64))
+                .and_then(|_| writer.write_all(&framed))
+                .map_err(FileError::io(&path))?;
+        }
+    }
+
+    Ok(reader.report)
+}
+
+/// Encodes the file at `path` stripe by stripe into `out_dir`, writing one
+/// file per shard index and a manifest. Memory use is a few stripes of
+/// `original_count * shard_bytes` bytes, regardless of the file size.
+/// Returns the manifest as a dict.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+pub(crate) fn encode_file(
+    py: Python<'_>,
+    path: PathBuf,
+    out_dir: PathBuf,
+    original_count: usize,
+    recovery_count: usize,
+    shard_bytes: usize,
+) -> PyResult<Py<PyDict>> {
+    let manifest =
+        py.allow_threads(|| encode(&path, &out_dir, original_count, recovery_count, shard_bytes))?;
+    Ok(manifest.to_dict(py)?.into())
+}
+
+/// Restores the file encoded by `encode_file` in `in_dir` to `out_path`,
+/// using any `original_count` intact shards of each stripe. Returns a dict
+/// with the sorted shard indices without a file as `"missing"`, and a list
+/// of `(index, stripes)` pairs for the damaged shards as `"damaged"`.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+pub(crate) fn decode_file(
+    py: Python<'_>,
+    in_dir: PathBuf,
+    out_path: PathBuf,
+) -> PyResult<Py<PyDict>> {
+    let report = py.allow_threads(|| decode(&in_dir, &out_path))?;
+    Ok(report.to_dict(py)?.into())
+}
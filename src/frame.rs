@@ -0,0 +1,314 @@
+//! Self-describing shards: each shard is prefixed with a fixed-size header
+//! holding everything needed to decode it together with its siblings.
+//!
+//! Header layout, all integers little-endian:
+//!
+//! | offset | size | field                        |
+//! | ------ | ---- | ---------------------------- |
+//! | 0      | 4    | magic `b"RSLP"`              |
+//! | 4      | 1    | version, currently 1         |
+//! | 5      | 1    | flags, bit 0 = is_recovery   |
+//! | 6      | 2    | reserved, zero               |
+//! | 8      | 4    | `original_count`             |
+//! | 12     | 4    | `recovery_count`             |
+//! | 16     | 4    | index (original or recovery) |
+//! | 20     | 4    | payload length               |
+//! | 24     | 8    | original blob length         |
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+use reed_solomon_simd::ReedSolomonDecoder;
+
+use std::collections::HashMap;
+
+use crate::blob;
+use crate::buffer::Shard;
+use crate::error::Error;
+
+const MAGIC: &[u8; 4] = b"RSLP";
+const VERSION: u8 = 1;
+pub(crate) const HEADER_BYTES: usize = 32;
+const FLAG_RECOVERY: u8 = 1;
+
+/// A framed shard split into its header and payload.
+pub(crate) type Parsed<'a> = (Header, &'a [u8]);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Header {
+    pub(crate) original_count: u32,
+    pub(crate) recovery_count: u32,
+    pub(crate) index: u32,
+    pub(crate) is_recovery: bool,
+    pub(crate) payload_bytes: u32,
+    pub(crate) length: u64,
+}
+
+impl Header {
+    pub(crate) fn new(
+        original_count: usize,
+        recovery_count: usize,
+        index: usize,
+        is_recovery: bool,
+        payload_bytes: usize,
+        length: u64,
+    ) -> PyResult<Self> {
+        Ok(Self {
+            original_count: to_u32("original_count", original_count)?,
+            recovery_count: to_u32("recovery_count", recovery_count)?,
+            index: to_u32("index", index)?,
+            is_recovery,
+            payload_bytes: to_u32("shard length", payload_bytes)?,
+            length,
+        })
+    }
+
+    pub(crate) fn write(&self, out: &mut [u8]) {
+        out[0..4].copy_from_slice(MAGIC);
+        out[4] = VERSION;
+        out[5] = if self.is_recovery { FLAG_RECOVERY } else { 0 };
+        out[6..8].fill(0);
+        out[8..12].copy_from_slice(&self.original_count.to_le_bytes());
+        out[12..16].copy_from_slice(&self.recovery_count.to_le_bytes());
+        out[16..20].copy_from_slice(&self.index.to_le_bytes());
+        out[20..24].copy_from_slice(&self.payload_bytes.to_le_bytes());
+        out[24..32].copy_from_slice(&self.length.to_le_bytes());
+    }
+
+    /// Reads the header at the start of `bytes`.
+    pub(crate) fn read(bytes: &[u8]) -> PyResult<Self> {
+        if bytes.len() < HEADER_BYTES {
+            return Err(PyValueError::new_err(format!(
+                "framed shard too short: {} bytes, header alone is {HEADER_BYTES}",
+                bytes.len()
+            )));
+        }
+        if &bytes[0..4] != MAGIC {
+            return Err(PyValueError::new_err("not a framed shard: bad magic"));
+        }
+        if bytes[4] != VERSION {
+            return Err(PyValueError::new_err(format!(
+                "unsupported framed shard version {}",
+                bytes[4]
+            )));
+        }
+        if bytes[5] & !FLAG_RECOVERY != 0 {
+            return Err(PyValueError::new_err(format!(
+                "framed shard has unknown flags {:#04x}",
+                bytes[5]
+            )));
+        }
+        if bytes[6..8] != [0, 0] {
+            return Err(PyValueError::new_err(
+                "framed shard has non-zero reserved bytes",
+            ));
+        }
+
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes(bytes[offset..offset + 4].try_into().expect("4 bytes"))
+        };
+        let header = Self {
+            original_count: u32_at(8),
+            recovery_count: u32_at(12),
+            index: u32_at(16),
+            is_recovery: bytes[5] & FLAG_RECOVERY != 0,
+            payload_bytes: u32_at(20),
+            length: u64::from_le_bytes(bytes[24..32].try_into().expect("8 bytes")),
+        };
+        header.check_index()?;
+        Ok(header)
+    }
+
+    /// Checks that the index is below the count of its kind of shard.
+    fn check_index(&self) -> PyResult<()> {
+        let (kind, count) = if self.is_recovery {
+            ("recovery", self.recovery_count)
+        } else {
+            ("original", self.original_count)
+        };
+        if self.index >= count {
+            return Err(PyValueError::new_err(format!(
+                "{kind} shard index {} is not below {kind} count {count}",
+                self.index
+            )));
+        }
+        Ok(())
+    }
+
+    /// Parses a framed shard into its header and payload.
+    pub(crate) fn parse(framed: &[u8]) -> PyResult<Parsed<'_>> {
+        let header = Self::read(framed)?;
+        let payload = &framed[HEADER_BYTES..];
+        if payload.len() != header.payload_bytes as usize {
+            return Err(PyValueError::new_err(format!(
+                "framed shard payload is {} bytes, header says {}",
+                payload.len(),
+                header.payload_bytes
+            )));
+        }
+
+        Ok((header, payload))
+    }
+
+    /// Whether two shards belong to the same stripe.
+    pub(crate) fn same_stripe(&self, other: &Self) -> bool {
+        Self {
+            index: other.index,
+            is_recovery: other.is_recovery,
+            ..*self
+        } == *other
+    }
+}
+
+pub(crate) fn to_u32(name: &str, value: usize) -> PyResult<u32> {
+    u32::try_from(value).map_err(|_| {
+        PyValueError::new_err(format!("{name} {value} does not fit in a shard header"))
+    })
+}
+
+/// Prefixes `shard` with a header. `length` is the length of the original
+/// data and defaults to `original_count * len(shard)`.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+#[pyo3(signature = (shard, original_count, recovery_count, index, is_recovery, length=None))]
+pub(crate) fn pack_shard(
+    py: Python<'_>,
+    shard: Shard,
+    original_count: usize,
+    recovery_count: usize,
+    index: usize,
+    is_recovery: bool,
+    length: Option<u64>,
+) -> PyResult<Py<PyBytes>> {
+    let payload = shard.as_ref();
+    let length = match length {
+        Some(length) => length,
+        None => original_count
+            .checked_mul(payload.len())
+            .and_then(|length| u64::try_from(length).ok())
+            .ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "{original_count} shards of {} bytes overflow the data length",
+                    payload.len()
+                ))
+            })?,
+    };
+    let header = Header::new(
+        original_count,
+        recovery_count,
+        index,
+        is_recovery,
+        payload.len(),
+        length,
+    )?;
+    header.check_index()?;
+    Ok(framed(py, &header, payload)?.into())
+}
+
+/// `payload` prefixed with `header`.
+pub(crate) fn framed<'py>(
+    py: Python<'py>,
+    header: &Header,
+    payload: &[u8],
+) -> PyResult<&'py PyBytes> {
+    PyBytes::new_with(py, HEADER_BYTES + payload.len(), |framed| {
+        header.write(framed);
+        framed[HEADER_BYTES..].copy_from_slice(payload);
+        Ok(())
+    })
+}
+
+/// Parses framed shards which must all belong to the same stripe. Returns
+/// the header of the first shard and the headers and payloads of all.
+pub(crate) fn parse_stripe(shards: &[Shard]) -> PyResult<(Header, Vec<Parsed<'_>>)> {
+    let parsed = shards
+        .iter()
+        .map(|framed| Header::parse(framed.as_ref()))
+        .collect::<PyResult<Vec<_>>>()?;
+
+    let Some(&(stripe, _)) = parsed.first() else {
+        return Err(PyValueError::new_err("no framed shards given"));
+    };
+    if let Some(i) = parsed
+        .iter()
+        .position(|(header, _)| !header.same_stripe(&stripe))
+    {
+        return Err(PyValueError::new_err(format!(
+            "framed shard {i} belongs to a different stripe than shard 0"
+        )));
+    }
+
+    Ok((stripe, parsed))
+}
+
+/// Splits a framed shard into a dict with the header fields and `payload`.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+pub(crate) fn unpack_shard(py: Python<'_>, framed: Shard) -> PyResult<Py<PyDict>> {
+    let (header, payload) = Header::parse(framed.as_ref())?;
+
+    let dict = PyDict::new(py);
+    dict.set_item("version", VERSION)?;
+    dict.set_item("original_count", header.original_count)?;
+    dict.set_item("recovery_count", header.recovery_count)?;
+    dict.set_item("index", header.index)?;
+    dict.set_item("is_recovery", header.is_recovery)?;
+    dict.set_item("length", header.length)?;
+    dict.set_item("payload", PyBytes::new(py, payload))?;
+    Ok(dict.into())
+}
+
+/// Restores the original data from a list of framed shards of one stripe,
+/// in any order. All parameters are read from the shard headers.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+pub(crate) fn decode_framed(py: Python<'_>, shards: Vec<Shard>) -> PyResult<Py<PyBytes>> {
+    let (stripe, parsed) = parse_stripe(&shards)?;
+
+    let original_count = stripe.original_count as usize;
+    let shard_bytes = stripe.payload_bytes as usize;
+    let length = usize::try_from(stripe.length)
+        .ok()
+        .filter(|&length| {
+            original_count
+                .checked_mul(shard_bytes)
+                .is_none_or(|capacity| length <= capacity)
+        })
+        .ok_or_else(|| {
+            PyValueError::new_err(format!(
+                "framed shard length {} does not fit in {original_count} shards of {shard_bytes} bytes",
+                stripe.length
+            ))
+        })?;
+
+    let mut decoder =
+        ReedSolomonDecoder::new(original_count, stripe.recovery_count as usize, shard_bytes)
+            .map_err(Error::from)?;
+
+    let decoder_result = py
+        .allow_threads(|| {
+            for (header, payload) in &parsed {
+                if header.is_recovery {
+                    decoder.add_recovery_shard(header.index as usize, payload)?;
+                } else {
+                    decoder.add_original_shard(header.index as usize, payload)?;
+                }
+            }
+            decoder.decode()
+        })
+        .map_err(Error::from)?;
+
+    let original: HashMap<usize, &[u8]> = parsed
+        .iter()
+        .filter(|(header, _)| !header.is_recovery)
+        .map(|&(header, payload)| (header.index as usize, payload))
+        .collect();
+
+    blob::assemble(py, length, shard_bytes, |idx| {
+        original
+            .get(&idx)
+            .copied()
+            .or_else(|| decoder_result.restored_original(idx))
+    })
+}
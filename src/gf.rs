@@ -0,0 +1,441 @@
+//! Arithmetic in GF(2^16) using the tables of `reed-solomon-simd`, so that
+//! the results match what its encoder and decoder compute.
+//!
+//! Shards are sequences of 16-bit symbols. Within every 64-byte chunk the
+//! first 32 bytes are the low bytes and the last 32 bytes the high bytes of
+//! 32 symbols. A final partial chunk of `n` bytes is split into `n / 2` low
+//! bytes followed by `n / 2` high bytes.
+//!
+//! Symbol by symbol, the shards of a stripe form a generalized Reed-Solomon
+//! codeword: the values of a polynomial of degree below `original_count` at
+//! distinct points, each scaled by a multiplier depending only on the point.
+//! With the field elements numbered as in the tables:
+//!
+//! - The high rate codec places the recovery shards at points
+//!   `0..recovery_count` and the original shards at `chunk..`, where `chunk`
+//!   is `recovery_count.next_power_of_two()`. All other points are zero
+//!   original shards of a plain Reed-Solomon code of length `2^16`.
+//! - The low rate codec places the original shards at points
+//!   `0..original_count` and the recovery shards at `chunk..`, where `chunk`
+//!   is `original_count.next_power_of_two()`. The points in
+//!   `original_count..chunk` are zero original shards of a plain
+//!   Reed-Solomon code of dimension `chunk`.
+
+use reed_solomon_simd::engine::{tables, GfElement, GF_MODULUS, GF_ORDER};
+
+pub(crate) fn mul(x: GfElement, y: GfElement) -> GfElement {
+    if x == 0 || y == 0 {
+        return 0;
+    }
+    let exp_log = tables::get_exp_log();
+    let log_sum = (u32::from(exp_log.log[usize::from(x)]) + u32::from(exp_log.log[usize::from(y)]))
+        % u32::from(GF_MODULUS);
+    exp_log.exp[log_sum as usize]
+}
+
+/// Multiplicative inverse of a non-zero element.
+pub(crate) fn inv(x: GfElement) -> GfElement {
+    debug_assert_ne!(x, 0);
+    let exp_log = tables::get_exp_log();
+    let log = exp_log.log[usize::from(x)];
+    exp_log.exp[usize::from((GF_MODULUS - log) % GF_MODULUS)]
+}
+
+fn element(value: usize) -> GfElement {
+    GfElement::try_from(value).expect("point is a field element")
+}
+
+/// `dst += factor * src` symbol by symbol, for buffers of plain
+/// little-endian 16-bit symbols rather than shards. `src` may be shorter.
+pub(crate) fn mul_add(dst: &mut [u8], src: &[u8], factor: GfElement) {
+    debug_assert!(src.len() <= dst.len());
+    if factor == 0 {
+        return;
+    }
+    for (dst, src) in dst.chunks_exact_mut(2).zip(src.chunks_exact(2)) {
+        let product = mul(factor, u16::from_le_bytes([src[0], src[1]]));
+        let sum = u16::from_le_bytes([dst[0], dst[1]]) ^ product;
+        dst.copy_from_slice(&sum.to_le_bytes());
+    }
+}
+
+/// `data *= factor` symbol by symbol, like `mul_add`.
+pub(crate) fn scale(data: &mut [u8], factor: GfElement) {
+    for symbol in data.chunks_exact_mut(2) {
+        let product = mul(factor, u16::from_le_bytes([symbol[0], symbol[1]]));
+        symbol.copy_from_slice(&product.to_le_bytes());
+    }
+}
+
+/// Byte offsets of the low and high byte of symbol `index`.
+fn symbol_offsets(shard_bytes: usize, index: usize) -> (usize, usize) {
+    let whole = shard_bytes / 64 * 32;
+    if index < whole {
+        let chunk = index / 32 * 64;
+        (chunk + index % 32, chunk + 32 + index % 32)
+    } else {
+        let tail = shard_bytes / 64 * 64;
+        let half = (shard_bytes % 64) / 2;
+        let pos = index - whole;
+        (tail + pos, tail + half + pos)
+    }
+}
+
+/// Symbol `index` of a shard.
+pub(crate) fn symbol(shard: &[u8], index: usize) -> GfElement {
+    let (lo, hi) = symbol_offsets(shard.len(), index);
+    u16::from_le_bytes([shard[lo], shard[hi]])
+}
+
+/// Smallest polynomial generating `syndromes`, lowest coefficient first.
+fn berlekamp_massey(syndromes: &[GfElement]) -> Vec<GfElement> {
+    let mut current = vec![1];
+    let mut previous = vec![1];
+    let mut previous_discrepancy = 1;
+    let mut length = 0;
+    let mut shift = 1;
+
+    for n in 0..syndromes.len() {
+        let discrepancy = (1..=length).fold(syndromes[n], |d, i| {
+            d ^ mul(current.get(i).copied().unwrap_or(0), syndromes[n - i])
+        });
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+
+        let factor = mul(discrepancy, inv(previous_discrepancy));
+        let before = current.clone();
+        current.resize(current.len().max(previous.len() + shift), 0);
+        for (i, &p) in previous.iter().enumerate() {
+            current[i + shift] ^= mul(factor, p);
+        }
+
+        if 2 * length <= n {
+            length = n + 1 - length;
+            previous = before;
+            previous_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            shift += 1;
+        }
+    }
+
+    current.resize(length + 1, 0);
+    current
+}
+
+/// The code of a stripe, see the module documentation, restricted to the
+/// shards received.
+pub(crate) struct Code {
+    original_count: usize,
+    high_rate: bool,
+    chunk: usize,
+    /// Positions of the received shards, sorted.
+    positions: Vec<usize>,
+    points: Vec<GfElement>,
+    /// Multipliers of the dual code on `points`.
+    dual: Vec<GfElement>,
+    /// A point of no received shard.
+    offset: GfElement,
+}
+
+impl Code {
+    /// The code of a stripe of which the shards at `positions` were
+    /// received. `locate_errors` takes symbols of these shards or any subset.
+    pub(crate) fn new(original_count: usize, recovery_count: usize, positions: &[usize]) -> Self {
+        // Same choice as `reed_solomon_simd::rate::DefaultRate`.
+        let high_rate = match original_count
+            .next_power_of_two()
+            .cmp(&recovery_count.next_power_of_two())
+        {
+            std::cmp::Ordering::Less => false,
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Equal => original_count <= recovery_count,
+        };
+        let chunk = if high_rate {
+            recovery_count.next_power_of_two()
+        } else {
+            original_count.next_power_of_two()
+        };
+
+        let mut code = Self {
+            original_count,
+            high_rate,
+            chunk,
+            positions: positions.to_vec(),
+            points: Vec::new(),
+            dual: Vec::new(),
+            offset: 0,
+        };
+        code.positions.sort_unstable();
+        code.points = code.positions.iter().map(|&p| code.point(p)).collect();
+
+        let mut is_received = vec![false; GF_ORDER];
+        for &point in &code.points {
+            is_received[usize::from(point)] = true;
+        }
+        code.dual = code.dual_multipliers(&code.points, &is_received);
+        code.offset = element(
+            is_received
+                .iter()
+                .position(|&received| !received)
+                .expect("at most 2^16 - 1 shards"),
+        );
+        code
+    }
+
+    /// Point of the shard at `position`: original shards first, then
+    /// recovery shards.
+    fn point(&self, position: usize) -> GfElement {
+        let point = match (self.high_rate, position < self.original_count) {
+            (true, true) => self.chunk + position,
+            (true, false) => position - self.original_count,
+            (false, true) => position,
+            (false, false) => self.chunk + position - self.original_count,
+        };
+        element(point)
+    }
+
+    /// Multipliers of the dual code on `points`, so that for every codeword
+    /// `c` and `l < points.len() - original_count`, the sum of
+    /// `c[p] * dual[p] * points[p]^l` is zero.
+    fn dual_multipliers(&self, points: &[GfElement], received: &[bool]) -> Vec<GfElement> {
+        let product = |x: GfElement, others: &mut dyn Iterator<Item = GfElement>| {
+            others.fold(1, |acc, w| mul(acc, x ^ w))
+        };
+
+        points
+            .iter()
+            .map(|&x| {
+                if self.high_rate {
+                    // Missing points of the `2^16` code are zero shards.
+                    product(
+                        x,
+                        &mut (0..self.chunk + self.original_count)
+                            .filter(|&w| !received[w])
+                            .map(element),
+                    )
+                } else {
+                    inv(product(
+                        x,
+                        &mut (self.original_count..self.chunk)
+                            .map(element)
+                            .chain(points.iter().copied())
+                            .filter(|&w| w != x),
+                    ))
+                }
+            })
+            .collect()
+    }
+
+    /// Points and dual multipliers of the shards at `positions`, a subset
+    /// of those the code was made for. Leaving out the point `y` multiplies
+    /// each other multiplier at `x` by `x + y`, for either rate.
+    fn subset(&self, positions: &[usize]) -> (Vec<GfElement>, Vec<GfElement>) {
+        let slots: Vec<usize> = positions
+            .iter()
+            .map(|position| {
+                self.positions
+                    .binary_search(position)
+                    .expect("position was received")
+            })
+            .collect();
+        let mut included = vec![false; self.positions.len()];
+        for &slot in &slots {
+            included[slot] = true;
+        }
+        let left_out: Vec<GfElement> = (0..self.points.len())
+            .filter(|&slot| !included[slot])
+            .map(|slot| self.points[slot])
+            .collect();
+
+        slots
+            .iter()
+            .map(|&slot| {
+                let x = self.points[slot];
+                let dual = left_out
+                    .iter()
+                    .fold(self.dual[slot], |acc, &y| mul(acc, x ^ y));
+                (x, dual)
+            })
+            .unzip()
+    }
+
+    /// Positions of the wrong symbols among the `(position, symbol)` pairs
+    /// of `received`, or `None` if there are too many to locate.
+    pub(crate) fn locate_errors(&self, received: &[(usize, GfElement)]) -> Option<Vec<usize>> {
+        let redundancy = received.len().checked_sub(self.original_count)?;
+
+        let positions: Vec<usize> = received.iter().map(|&(position, _)| position).collect();
+        let (points, dual) = self.subset(&positions);
+
+        // Shifting all points keeps the code, and makes them non-zero so
+        // that each error location is a root of the locator polynomial.
+        let offset = self.offset;
+
+        let mut syndromes = vec![0; redundancy];
+        for ((&(_, symbol), &point), &multiplier) in received.iter().zip(&points).zip(&dual) {
+            let shifted = point ^ offset;
+            let mut term = mul(symbol, multiplier);
+            for syndrome in &mut syndromes {
+                *syndrome ^= term;
+                term = mul(term, shifted);
+            }
+        }
+
+        let locator = berlekamp_massey(&syndromes);
+        let error_count = locator.len() - 1;
+        if 2 * error_count > redundancy {
+            return None;
+        }
+
+        // `point ^ offset` is an error location if its inverse is a root of
+        // the locator, i.e. if it is a root of the reversed locator.
+        let errors: Vec<usize> = received
+            .iter()
+            .zip(&points)
+            .filter(|&(_, &point)| {
+                let shifted = point ^ offset;
+                locator.iter().fold(0, |acc, &c| mul(acc, shifted) ^ c) == 0
+            })
+            .map(|(&(position, _), _)| position)
+            .collect();
+
+        (errors.len() == error_count).then_some(errors)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use reed_solomon_simd::ReedSolomonEncoder;
+
+    use crate::random::Random;
+
+    /// The shards of a random stripe, original shards first.
+    pub(crate) fn stripe(
+        random: &mut Random,
+        original_count: usize,
+        recovery_count: usize,
+        shard_bytes: usize,
+    ) -> Vec<Vec<u8>> {
+        let mut shards: Vec<Vec<u8>> = (0..original_count)
+            .map(|_| random.bytes(shard_bytes))
+            .collect();
+        let mut encoder =
+            ReedSolomonEncoder::new(original_count, recovery_count, shard_bytes).unwrap();
+        for shard in &shards {
+            encoder.add_original_shard(shard).unwrap();
+        }
+        let recovery: Vec<Vec<u8>> = encoder
+            .encode()
+            .unwrap()
+            .recovery_iter()
+            .map(<[u8]>::to_vec)
+            .collect();
+        shards.extend(recovery);
+        shards
+    }
+
+    /// High and low rate codes, with odd and even redundancy.
+    pub(crate) const SHARD_COUNTS: [(usize, usize); 6] =
+        [(4, 4), (10, 6), (16, 5), (7, 2), (3, 8), (5, 11)];
+
+    #[test]
+    fn field_arithmetic() {
+        for x in (1..=u16::MAX).step_by(97) {
+            assert_eq!(mul(x, inv(x)), 1, "{x}");
+            assert_eq!(mul(x, 1), x);
+            assert_eq!(mul(x, 0), 0);
+        }
+        for (x, y, z) in [(3, 7, 11), (0x1234, 0xFFFF, 0x8000), (1, 2, 0xABCD)] {
+            assert_eq!(mul(x, y), mul(y, x));
+            assert_eq!(mul(x, y ^ z), mul(x, y) ^ mul(x, z));
+            assert_eq!(mul(mul(x, y), z), mul(x, mul(y, z)));
+        }
+    }
+
+    #[test]
+    fn symbol_layout() {
+        // A whole chunk, then a tail of 3 low and 3 high bytes.
+        let mut shard = vec![0; 70];
+        shard[5] = 0x34;
+        shard[37] = 0x12;
+        shard[65] = 0x78;
+        shard[68] = 0x56;
+        assert_eq!(symbol(&shard, 5), 0x1234);
+        assert_eq!(symbol(&shard, 33), 0x5678);
+    }
+
+    /// Corrupts symbol `index` of `corrupted` random shards of a stripe and
+    /// returns what `locate_errors` finds, and the corrupted positions.
+    fn locate_corrupted(
+        seed: u64,
+        (original_count, recovery_count): (usize, usize),
+        index: usize,
+        corrupted: usize,
+    ) -> (Option<Vec<usize>>, Vec<usize>) {
+        let mut random = Random(seed);
+        let shards = stripe(&mut random, original_count, recovery_count, 70);
+        let mut received: Vec<(usize, GfElement)> = shards
+            .iter()
+            .enumerate()
+            .map(|(position, shard)| (position, symbol(shard, index)))
+            .collect();
+
+        let mut expected: Vec<usize> = random.shuffled(received.len())[..corrupted].to_vec();
+        expected.sort_unstable();
+        for &position in &expected {
+            let error = GfElement::try_from(random.below(usize::from(GF_MODULUS)) + 1).unwrap();
+            received[position].1 ^= error;
+        }
+
+        let positions: Vec<usize> = (0..received.len()).collect();
+        let code = Code::new(original_count, recovery_count, &positions);
+        (code.locate_errors(&received), expected)
+    }
+
+    #[test]
+    fn subsets_match_their_own_code() {
+        let mut random = Random(4);
+        for (original_count, recovery_count) in SHARD_COUNTS {
+            let shard_count = original_count + recovery_count;
+            let all: Vec<usize> = (0..shard_count).collect();
+            let code = Code::new(original_count, recovery_count, &all);
+            for left_out in 0..=recovery_count {
+                let mut subset = random.shuffled(shard_count)[left_out..].to_vec();
+                subset.sort_unstable();
+                let own = Code::new(original_count, recovery_count, &subset);
+                assert_eq!(
+                    code.subset(&subset),
+                    (own.points.clone(), own.dual.clone()),
+                    "{original_count} {recovery_count} {subset:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn locates_up_to_half_the_redundancy() {
+        for counts in SHARD_COUNTS {
+            for corrupted in 0..=counts.1 / 2 {
+                for (seed, index) in [(1, 0), (2, 17), (3, 33)] {
+                    let (located, expected) = locate_corrupted(seed, counts, index, corrupted);
+                    assert_eq!(located, Some(expected), "{counts:?} {corrupted} {seed}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_more_than_half_the_redundancy() {
+        for counts in SHARD_COUNTS {
+            for (seed, index) in [(1, 0), (2, 17), (3, 33)] {
+                let (located, _) = locate_corrupted(seed, counts, index, counts.1 / 2 + 1);
+                assert_eq!(located, None, "{counts:?} {seed}");
+            }
+        }
+    }
+}
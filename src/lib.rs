@@ -1,80 +1,195 @@
 #![warn(clippy::pedantic)]
+// pyo3 0.20 expands `#[new]` into impl blocks nested in functions.
+#![allow(non_local_definitions)]
 
 use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use pyo3::types::{PyBytes, PyDict, PyList};
 
+use reed_solomon_simd::engine::DefaultEngine;
+use reed_solomon_simd::rate::{DefaultRateDecoder, DefaultRateEncoder, RateDecoder, RateEncoder};
 use reed_solomon_simd::ReedSolomonDecoder;
 use reed_solomon_simd::ReedSolomonEncoder;
+use reed_solomon_simd::{DecoderResult, EncoderResult};
 
 use std::collections::HashMap;
 
-struct Error(reed_solomon_simd::Error);
-
-impl From<reed_solomon_simd::Error> for Error {
-    fn from(other: reed_solomon_simd::Error) -> Self {
-        Self(other)
-    }
-}
-
-impl From<Error> for PyErr {
-    fn from(error: Error) -> Self {
-        PyValueError::new_err(error.0.to_string())
-    }
-}
+#[cfg(feature = "numpy")]
+mod array;
+mod blob;
+mod buffer;
+mod checksum;
+#[doc(hidden)]
+pub mod cli;
+mod compat;
+mod correct;
+mod durability;
+mod error;
+mod extend;
+mod fec;
+mod file;
+mod frame;
+mod gf;
+mod plan;
+mod random;
+mod repair;
+mod striping;
+mod volume;
+mod window;
+use buffer::{Shard, ShardMut};
+use error::Error;
 
 #[pyfunction]
 fn supports(original_count: usize, recovery_count: usize) -> bool {
     ReedSolomonEncoder::supports(original_count, recovery_count)
 }
 
-#[pyfunction]
-fn encode(data: Vec<&[u8]>, recovery_count: usize) -> Result<Py<PyList>, Error> {
-    let original_count = data.len();
-    let mut original_iter = data.into_iter();
-
-    let first = original_iter
-        .next()
+/// Creates an encoder for `recovery_count` recovery shards of `original`,
+/// which sets the shard size and must not be empty.
+pub(crate) fn new_encoder<E: RateEncoder<DefaultEngine>>(
+    original: &[Shard],
+    recovery_count: usize,
+) -> Result<E, Error> {
+    let original_count = original.len();
+    let first = original
+        .first()
         .ok_or(reed_solomon_simd::Error::TooFewOriginalShards {
             original_count,
             original_received_count: 0,
         })?;
-    let shard_bytes = first.len();
+    Ok(E::new(
+        original_count,
+        recovery_count,
+        first.as_ref().len(),
+        DefaultEngine::new(),
+        None,
+    )?)
+}
 
-    let mut encoder = ReedSolomonEncoder::new(original_count, recovery_count, shard_bytes)?;
+/// Adds `original` to `encoder` and encodes without holding the GIL.
+pub(crate) fn encode_shards<'e, E: RateEncoder<DefaultEngine> + Send>(
+    py: Python<'_>,
+    encoder: &'e mut E,
+    original: &[Shard],
+) -> Result<EncoderResult<'e>, Error> {
+    // The input buffers stay exported until `original` is dropped, so they
+    // can be read without holding the GIL. Iterate by reference so that they
+    // are released after the GIL is reacquired.
+    Ok(py.allow_threads(|| {
+        for original_shard in original {
+            encoder.add_original_shard(original_shard)?;
+        }
+        encoder.encode()
+    })?)
+}
 
-    encoder.add_original_shard(first)?;
-    for original_shard in original_iter {
-        encoder.add_original_shard(original_shard)?;
-    }
+/// Creates a decoder for shards of the size of those in `recovery`, which
+/// must not be empty.
+pub(crate) fn new_decoder<D: RateDecoder<DefaultEngine>, S: AsRef<[u8]>>(
+    original_count: usize,
+    recovery_count: usize,
+    original: &HashMap<usize, S>,
+    recovery: &HashMap<usize, S>,
+) -> Result<D, Error> {
+    let Some(first_recovery) = recovery.values().next() else {
+        return Err(Error(reed_solomon_simd::Error::NotEnoughShards {
+            original_count,
+            original_received_count: original.len(),
+            recovery_received_count: 0,
+        }));
+    };
+    Ok(D::new(
+        original_count,
+        recovery_count,
+        first_recovery.as_ref().len(),
+        DefaultEngine::new(),
+        None,
+    )?)
+}
+
+/// Adds `original` and `recovery` to `decoder` and decodes without holding
+/// the GIL.
+pub(crate) fn decode_shards<'d, D: RateDecoder<DefaultEngine> + Send, S: AsRef<[u8]> + Sync>(
+    py: Python<'_>,
+    decoder: &'d mut D,
+    original: &HashMap<usize, S>,
+    recovery: &HashMap<usize, S>,
+) -> Result<DecoderResult<'d>, Error> {
+    // As in `encode_shards`, the buffers stay exported for the whole call.
+    Ok(py.allow_threads(|| {
+        for (&idx, shard) in original {
+            decoder.add_original_shard(idx, shard)?;
+        }
+        for (&idx, shard) in recovery {
+            decoder.add_recovery_shard(idx, shard)?;
+        }
+        decoder.decode()
+    })?)
+}
 
-    let encoder_result = encoder.encode()?;
+// pyo3 extracts arguments by value.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+fn encode(py: Python<'_>, data: Vec<Shard>, recovery_count: usize) -> Result<Py<PyList>, Error> {
+    let mut encoder: DefaultRateEncoder<_> = new_encoder(&data, recovery_count)?;
+    let encoder_result = encode_shards(py, &mut encoder, &data)?;
 
-    Python::with_gil(|py| {
-        let recovery_shards: Vec<&PyBytes> = encoder_result
-            .recovery_iter()
-            .map(|s| PyBytes::new(py, s))
-            .collect();
-        Ok(PyList::new(py, recovery_shards).into())
-    })
+    let recovery_shards: Vec<&PyBytes> = encoder_result
+        .recovery_iter()
+        .map(|s| PyBytes::new(py, s))
+        .collect();
+    Ok(PyList::new(py, recovery_shards).into())
 }
 
+#[allow(clippy::needless_pass_by_value)]
 #[pyfunction]
 fn decode(
+    py: Python<'_>,
     original_count: usize,
     recovery_count: usize,
-    original: HashMap<usize, &[u8]>,
-    recovery: HashMap<usize, &[u8]>,
+    original: HashMap<usize, Shard>,
+    recovery: HashMap<usize, Shard>,
 ) -> PyResult<Py<PyDict>> {
     // HashMap implements ExactSizeIterator, so .len() is O(1)
     if original.len() == original_count {
         // Nothing to do, original data is complete.
-        return Python::with_gil(|py| Ok(PyDict::new(py).into()));
+        return Ok(PyDict::new(py).into());
     }
 
-    let mut recovery_iter = recovery.into_iter();
+    let mut decoder: DefaultRateDecoder<_> =
+        new_decoder(original_count, recovery_count, &original, &recovery)?;
+    let decoder_result = decode_shards(py, &mut decoder, &original, &recovery)?;
 
-    let Some((first_recovery_idx, first_recovery)) = recovery_iter.next() else {
+    let py_dict = PyDict::new(py);
+    for (idx, shard) in decoder_result.restored_original_iter() {
+        py_dict.set_item(idx, PyBytes::new(py, shard))?;
+    }
+    Ok(py_dict.into())
+}
+
+/// Shard bytes are processed in chunks of 64, each a column of its own.
+const CHUNK_BYTES: usize = 64;
+
+/// Like `decode`, but only reads bytes `offset` to `offset + length` of each
+/// shard and returns just those bytes of the missing original shards. Each
+/// 64-byte chunk of a shard is decoded on its own, so `offset` must be a
+/// multiple of 64, and `length` too unless the range ends with the shards.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+fn decode_range(
+    py: Python<'_>,
+    original_count: usize,
+    recovery_count: usize,
+    original: HashMap<usize, Shard>,
+    recovery: HashMap<usize, Shard>,
+    offset: usize,
+    length: usize,
+) -> PyResult<Py<PyDict>> {
+    if original.len() == original_count {
+        return Ok(PyDict::new(py).into());
+    }
+
+    let Some(first_recovery) = recovery.values().next() else {
         return Err(Error(reed_solomon_simd::Error::NotEnoughShards {
             original_count,
             original_received_count: original.len(),
@@ -82,44 +197,380 @@ fn decode(
         })
         .into());
     };
+    let shard_bytes = first_recovery.as_ref().len();
+    if let Some(shard) = original
+        .values()
+        .chain(recovery.values())
+        .find(|shard| shard.as_ref().len() != shard_bytes)
+    {
+        return Err(Error(reed_solomon_simd::Error::DifferentShardSize {
+            shard_bytes,
+            got: shard.as_ref().len(),
+        })
+        .into());
+    }
+
+    let end = offset.checked_add(length).filter(|&end| end <= shard_bytes);
+    let Some(end) = end.filter(|_| length > 0) else {
+        return Err(PyValueError::new_err(format!(
+            "range of {length} bytes at offset {offset} is empty or past the end of \
+             {shard_bytes}-byte shards"
+        )));
+    };
+    if !offset.is_multiple_of(CHUNK_BYTES)
+        || !(length.is_multiple_of(CHUNK_BYTES) || end == shard_bytes)
+    {
+        return Err(PyValueError::new_err(format!(
+            "range of {length} bytes at offset {offset} is not aligned: offset must be \
+             a multiple of {CHUNK_BYTES}, and length too unless the range ends with the shards"
+        )));
+    }
+
+    let original: HashMap<usize, &[u8]> = original
+        .iter()
+        .map(|(&idx, shard)| (idx, &shard.as_ref()[offset..end]))
+        .collect();
+    let recovery: HashMap<usize, &[u8]> = recovery
+        .iter()
+        .map(|(&idx, shard)| (idx, &shard.as_ref()[offset..end]))
+        .collect();
+    let mut decoder: DefaultRateDecoder<_> =
+        new_decoder(original_count, recovery_count, &original, &recovery)?;
+    let decoder_result = decode_shards(py, &mut decoder, &original, &recovery)?;
+
+    let py_dict = PyDict::new(py);
+    for (idx, shard) in decoder_result.restored_original_iter() {
+        py_dict.set_item(idx, PyBytes::new(py, shard))?;
+    }
+    Ok(py_dict.into())
+}
+
+/// Re-derives the recovery shards from `original` and returns the indices of
+/// the shards in `recovery` which don't match.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+fn verify(py: Python<'_>, original: Vec<Shard>, recovery: Vec<Shard>) -> PyResult<Vec<usize>> {
+    let mut encoder: DefaultRateEncoder<_> = new_encoder(&original, recovery.len())?;
+    let encoder_result = encode_shards(py, &mut encoder, &original)?;
+    py.allow_threads(|| mismatched_recovery(&encoder_result, &recovery))
+}
+
+/// Indices of the shards in `recovery` which differ from the ones in
+/// `encoder_result`.
+fn mismatched_recovery(encoder_result: &EncoderResult, recovery: &[Shard]) -> PyResult<Vec<usize>> {
+    let expected: Vec<&[u8]> = encoder_result.recovery_iter().collect();
+    if expected.len() != recovery.len() {
+        return Err(PyValueError::new_err(format!(
+            "expected {} recovery shards, got {}",
+            expected.len(),
+            recovery.len()
+        )));
+    }
+
+    let mut mismatched = Vec::new();
+    for (idx, (expected, shard)) in expected.iter().zip(recovery).enumerate() {
+        if shard.as_ref().len() != expected.len() {
+            return Err(Error(reed_solomon_simd::Error::DifferentShardSize {
+                shard_bytes: expected.len(),
+                got: shard.as_ref().len(),
+            })
+            .into());
+        }
+        if shard.as_ref() != *expected {
+            mismatched.push(idx);
+        }
+    }
+    Ok(mismatched)
+}
+
+/// Like `encode`, but writes the recovery shards into the preallocated,
+/// writable buffers in `recovery_out` instead of allocating new `bytes`.
+/// `len(recovery_out)` is used as the recovery count.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+fn encode_into(py: Python<'_>, data: Vec<Shard>, mut recovery_out: Vec<ShardMut>) -> PyResult<()> {
+    let mut encoder: DefaultRateEncoder<_> = new_encoder(&data, recovery_out.len())?;
+    let shard_bytes = data[0].as_ref().len();
 
-    let mut decoder = ReedSolomonDecoder::new(original_count, recovery_count, first_recovery.len())
-        .map_err(Error::from)?;
+    for out in &recovery_out {
+        check_output_size(shard_bytes, out)?;
+    }
+    buffer::ensure_disjoint(&data, &recovery_out)?;
+
+    let encoder_result = encode_shards(py, &mut encoder, &data)?;
+    py.allow_threads(|| {
+        for (out, recovery_shard) in recovery_out.iter_mut().zip(encoder_result.recovery_iter()) {
+            out.as_mut().copy_from_slice(recovery_shard);
+        }
+    });
+    Ok(())
+}
 
-    // Add original shards
-    for (idx, shard) in original {
-        decoder
-            .add_original_shard(idx, shard)
-            .map_err(Error::from)?;
+/// Like `decode`, but writes each restored original shard into
+/// `restored_out[idx]`. `restored_out` must hold a preallocated, writable
+/// buffer for exactly the original indices missing from `original`.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+fn decode_into(
+    py: Python<'_>,
+    original_count: usize,
+    recovery_count: usize,
+    original: HashMap<usize, Shard>,
+    recovery: HashMap<usize, Shard>,
+    mut restored_out: HashMap<usize, ShardMut>,
+) -> PyResult<()> {
+    for idx in 0..original_count {
+        if !original.contains_key(&idx) && !restored_out.contains_key(&idx) {
+            return Err(PyValueError::new_err(format!(
+                "restored_out has no buffer for missing original shard {idx}"
+            )));
+        }
+    }
+    if let Some(idx) = restored_out
+        .keys()
+        .find(|&idx| *idx >= original_count || original.contains_key(idx))
+    {
+        return Err(PyValueError::new_err(format!(
+            "restored_out has a buffer for original shard {idx}, which is not missing"
+        )));
     }
 
-    // Add recovery shards
-    decoder
-        .add_recovery_shard(first_recovery_idx, first_recovery)
-        .map_err(Error::from)?;
-    for (idx, shard) in recovery_iter {
-        decoder
-            .add_recovery_shard(idx, shard)
-            .map_err(Error::from)?;
+    if restored_out.is_empty() {
+        // Nothing to do, original data is complete.
+        return Ok(());
     }
 
-    // Decode
-    let decoder_result = decoder.decode().map_err(Error::from)?;
+    let mut decoder: DefaultRateDecoder<_> =
+        new_decoder(original_count, recovery_count, &original, &recovery)?;
+    let shard_bytes = recovery
+        .values()
+        .next()
+        .map_or(0, |shard| shard.as_ref().len());
+
+    for out in restored_out.values() {
+        check_output_size(shard_bytes, out)?;
+    }
+    buffer::ensure_disjoint(
+        original.values().chain(recovery.values()),
+        restored_out.values(),
+    )?;
 
-    Python::with_gil(|py| {
+    let decoder_result = decode_shards(py, &mut decoder, &original, &recovery)?;
+    py.allow_threads(|| {
+        for (idx, restored_shard) in decoder_result.restored_original_iter() {
+            if let Some(out) = restored_out.get_mut(&idx) {
+                out.as_mut().copy_from_slice(restored_shard);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn check_output_size(shard_bytes: usize, out: &ShardMut) -> Result<(), Error> {
+    if out.len() == shard_bytes {
+        Ok(())
+    } else {
+        Err(Error(reed_solomon_simd::Error::DifferentShardSize {
+            shard_bytes,
+            got: out.len(),
+        }))
+    }
+}
+
+/// Reusable encoder. Allocates its working space once, so encoding many
+/// stripes with the same (or smaller) configuration avoids the setup cost
+/// that [`encode`] pays on every call.
+#[pyclass]
+struct Encoder(ReedSolomonEncoder);
+
+#[pymethods]
+impl Encoder {
+    #[new]
+    fn new(
+        original_count: usize,
+        recovery_count: usize,
+        shard_bytes: usize,
+    ) -> Result<Self, Error> {
+        Ok(Self(ReedSolomonEncoder::new(
+            original_count,
+            recovery_count,
+            shard_bytes,
+        )?))
+    }
+
+    fn add_original_shard(&mut self, original_shard: Shard) -> Result<(), Error> {
+        Ok(self.0.add_original_shard(original_shard)?)
+    }
+
+    /// Returns the recovery shards. Afterwards the encoder is ready for the
+    /// next stripe with the same configuration.
+    fn encode(&mut self, py: Python<'_>) -> Result<Py<PyList>, Error> {
+        let encoder = &mut self.0;
+        let encoder_result = py.allow_threads(|| encoder.encode())?;
+        let recovery_shards: Vec<&PyBytes> = encoder_result
+            .recovery_iter()
+            .map(|s| PyBytes::new(py, s))
+            .collect();
+        Ok(PyList::new(py, recovery_shards).into())
+    }
+
+    /// Like `encode`, but compares the recovery shards with `recovery` and
+    /// returns the indices of those which don't match.
+    #[allow(clippy::needless_pass_by_value)]
+    fn verify(&mut self, py: Python<'_>, recovery: Vec<Shard>) -> PyResult<Vec<usize>> {
+        let encoder = &mut self.0;
+        py.allow_threads(|| {
+            let encoder_result = encoder.encode().map_err(Error::from)?;
+            mismatched_recovery(&encoder_result, &recovery)
+        })
+    }
+
+    /// Forgets any added shards and switches to a new configuration,
+    /// re-using the existing working space if it's large enough.
+    fn reset(
+        &mut self,
+        original_count: usize,
+        recovery_count: usize,
+        shard_bytes: usize,
+    ) -> Result<(), Error> {
+        Ok(self.0.reset(original_count, recovery_count, shard_bytes)?)
+    }
+}
+
+/// Reusable decoder which accepts shards one at a time, e.g. as they
+/// arrive from the network.
+#[pyclass]
+struct Decoder {
+    inner: ReedSolomonDecoder,
+    original_count: usize,
+    original_received_count: usize,
+    recovery_received_count: usize,
+}
+
+#[pymethods]
+impl Decoder {
+    #[new]
+    fn new(
+        original_count: usize,
+        recovery_count: usize,
+        shard_bytes: usize,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            inner: ReedSolomonDecoder::new(original_count, recovery_count, shard_bytes)?,
+            original_count,
+            original_received_count: 0,
+            recovery_received_count: 0,
+        })
+    }
+
+    fn add_original_shard(&mut self, index: usize, original_shard: Shard) -> Result<(), Error> {
+        self.inner.add_original_shard(index, original_shard)?;
+        self.original_received_count += 1;
+        Ok(())
+    }
+
+    fn add_recovery_shard(&mut self, index: usize, recovery_shard: Shard) -> Result<(), Error> {
+        self.inner.add_recovery_shard(index, recovery_shard)?;
+        self.recovery_received_count += 1;
+        Ok(())
+    }
+
+    /// `True` once enough shards have been added to restore the original data.
+    #[getter]
+    fn can_decode(&self) -> bool {
+        self.missing_count() == 0
+    }
+
+    /// Number of additional shards (original or recovery) needed before
+    /// `decode()` can succeed.
+    #[getter]
+    fn missing_count(&self) -> usize {
+        self.original_count
+            .saturating_sub(self.original_received_count + self.recovery_received_count)
+    }
+
+    /// Returns a dict with the restored original shards. Afterwards the
+    /// decoder is ready for the next stripe with the same configuration.
+    fn decode(&mut self, py: Python<'_>) -> PyResult<Py<PyDict>> {
         let py_dict = PyDict::new(py);
+        let decoder = &mut self.inner;
+        let decoder_result = py.allow_threads(|| decoder.decode()).map_err(Error::from)?;
         for (idx, shard) in decoder_result.restored_original_iter() {
             py_dict.set_item(idx, PyBytes::new(py, shard))?;
         }
+        drop(decoder_result);
+        self.clear_received();
+
         Ok(py_dict.into())
-    })
+    }
+
+    /// Forgets any added shards and switches to a new configuration,
+    /// re-using the existing working space if it's large enough.
+    fn reset(
+        &mut self,
+        original_count: usize,
+        recovery_count: usize,
+        shard_bytes: usize,
+    ) -> Result<(), Error> {
+        self.inner
+            .reset(original_count, recovery_count, shard_bytes)?;
+        self.original_count = original_count;
+        self.clear_received();
+        Ok(())
+    }
 }
 
-/// Python bindings to https://crates.io/crates/reed-solomon-simd
+impl Decoder {
+    fn clear_received(&mut self) {
+        self.original_received_count = 0;
+        self.recovery_received_count = 0;
+    }
+}
+
+/// Python bindings to <https://crates.io/crates/reed-solomon-simd>
 #[pymodule]
-fn reed_solomon_leopard(_py: Python, m: &PyModule) -> PyResult<()> {
+fn reed_solomon_leopard(py: Python, m: &PyModule) -> PyResult<()> {
+    error::register(py, m)?;
+    compat::register(py, m)?;
     m.add_function(wrap_pyfunction!(supports, m)?)?;
     m.add_function(wrap_pyfunction!(encode, m)?)?;
     m.add_function(wrap_pyfunction!(decode, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_range, m)?)?;
+    m.add_function(wrap_pyfunction!(verify, m)?)?;
+    m.add_function(wrap_pyfunction!(encode_into, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_into, m)?)?;
+    m.add_function(wrap_pyfunction!(blob::encode_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(blob::decode_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(frame::pack_shard, m)?)?;
+    m.add_function(wrap_pyfunction!(frame::unpack_shard, m)?)?;
+    m.add_function(wrap_pyfunction!(frame::decode_framed, m)?)?;
+    m.add_function(wrap_pyfunction!(checksum::checksum, m)?)?;
+    m.add_function(wrap_pyfunction!(checksum::encode_checksummed, m)?)?;
+    m.add_function(wrap_pyfunction!(checksum::decode_checksummed, m)?)?;
+    m.add_function(wrap_pyfunction!(correct::decode_correcting, m)?)?;
+    m.add_function(wrap_pyfunction!(repair::repair, m)?)?;
+    m.add_function(wrap_pyfunction!(repair::reconstruct, m)?)?;
+    m.add_function(wrap_pyfunction!(file::encode_file, m)?)?;
+    m.add_function(wrap_pyfunction!(file::decode_file, m)?)?;
+    m.add_function(wrap_pyfunction!(volume::write_volume, m)?)?;
+    m.add_function(wrap_pyfunction!(plan::plan, m)?)?;
+    m.add_function(wrap_pyfunction!(durability::durability, m)?)?;
+    m.add_function(wrap_pyfunction!(durability::min_recovery_for, m)?)?;
+    m.add_function(wrap_pyfunction!(extend::encode_recovery_range, m)?)?;
+    m.add_function(wrap_pyfunction!(extend::decode_extended, m)?)?;
+    #[cfg(feature = "numpy")]
+    {
+        m.add_function(wrap_pyfunction!(array::encode_array, m)?)?;
+        m.add_function(wrap_pyfunction!(array::decode_array, m)?)?;
+    }
+    m.add_class::<Encoder>()?;
+    m.add_class::<Decoder>()?;
+    m.add_class::<volume::Volume>()?;
+    m.add_class::<striping::Striping>()?;
+    m.add_class::<plan::Plan>()?;
+    m.add_class::<fec::FecSender>()?;
+    m.add_class::<fec::FecReceiver>()?;
+    m.add_class::<window::SlidingWindowSender>()?;
+    m.add_class::<window::SlidingWindowReceiver>()?;
     Ok(())
 }
@@ -0,0 +1,190 @@
+//! Choosing `original_count`, `recovery_count` and `shard_bytes` for data of
+//! a given length, within the limits of `supports` and the shard size rules.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::striping::ceil_count;
+
+/// Shard sizes must be a multiple of this.
+const SHARD_BYTES_MULTIPLE: usize = 2;
+/// Shard sizes which are a multiple of this avoid the slower handling of a
+/// partial 64-byte chunk at the end of each shard.
+const ALIGNED_SHARD_BYTES: usize = 64;
+
+/// Parameters for encoding data of `data_len` bytes as a single stripe,
+/// chosen by `plan`.
+#[pyclass(module = "reed_solomon_leopard", frozen, get_all)]
+pub(crate) struct Plan {
+    original_count: usize,
+    recovery_count: usize,
+    shard_bytes: usize,
+    /// Zero bytes appended to the data to fill the last original shard.
+    padding: usize,
+    /// `recovery_count / original_count`.
+    overhead: f64,
+    /// Why other choices were rejected, in the order they were made.
+    rejected: Vec<String>,
+}
+
+#[pymethods]
+impl Plan {
+    fn __repr__(&self) -> String {
+        format!(
+            "Plan(original_count={}, recovery_count={}, shard_bytes={}, padding={}, overhead={})",
+            self.original_count, self.recovery_count, self.shard_bytes, self.padding, self.overhead
+        )
+    }
+}
+
+fn round_up(value: usize, multiple: usize) -> usize {
+    value.max(1).div_ceil(multiple) * multiple
+}
+
+/// Recovery shards for `original_count` original shards at `overhead`.
+#[allow(clippy::cast_precision_loss)]
+fn recovery_for(original_count: usize, overhead: f64) -> usize {
+    ceil_count(original_count as f64 * overhead)
+}
+
+/// Fewest original shards for `recovery_count` recovery shards at `overhead`.
+#[allow(clippy::cast_precision_loss)]
+fn original_for(recovery_count: usize, overhead: f64) -> usize {
+    ceil_count(recovery_count as f64 / overhead)
+}
+
+/// Fewest original shards from `min_original_count` on for which
+/// `recovery_for` stays within `overhead`, if any are supported.
+fn original_within(min_original_count: usize, overhead: f64) -> Option<usize> {
+    (min_original_count..usize::MAX)
+        .map(|original_count| (original_count, recovery_for(original_count, overhead)))
+        .take_while(|&(original_count, recovery_count)| {
+            crate::supports(original_count, recovery_count)
+        })
+        .find(|&(original_count, recovery_count)| {
+            original_for(recovery_count, overhead) <= original_count
+        })
+        .map(|(original_count, _)| original_count)
+}
+
+/// Chooses the fewest original shards that fit the data in shards of at
+/// most `max_shard_bytes`, with `tolerate_losses` recovery shards.
+/// `overhead` is the largest allowed ratio of recovery to original shards.
+/// If only `overhead` is given, the recovery count is `ceil(original_count *
+/// overhead)`, with the fewest original shards for which that ratio stays
+/// within `overhead`, e.g. 2 original shards for an overhead of 0.5.
+/// With `prefer_aligned`, shard sizes are rounded up to a multiple of 64
+/// bytes when that stays within `max_shard_bytes`, otherwise to an even
+/// number of bytes. Raises `ValueError` explaining why if no parameters fit.
+#[allow(clippy::cast_precision_loss)]
+#[pyfunction]
+#[pyo3(signature = (
+    data_len,
+    tolerate_losses=None,
+    overhead=None,
+    max_shard_bytes=None,
+    prefer_aligned=true,
+))]
+pub(crate) fn plan(
+    data_len: usize,
+    tolerate_losses: Option<usize>,
+    overhead: Option<f64>,
+    max_shard_bytes: Option<usize>,
+    prefer_aligned: bool,
+) -> PyResult<Plan> {
+    if tolerate_losses.is_none() && overhead.is_none() {
+        return Err(PyValueError::new_err(
+            "give tolerate_losses, overhead or both",
+        ));
+    }
+    if let Some(overhead) = overhead {
+        if !(overhead.is_finite() && overhead > 0.0) {
+            return Err(PyValueError::new_err(format!(
+                "overhead must be a positive number, got {overhead}"
+            )));
+        }
+    }
+    if tolerate_losses == Some(0) {
+        return Err(PyValueError::new_err(
+            "tolerate_losses must be at least 1, a code needs a recovery shard",
+        ));
+    }
+    let max_shard_bytes = max_shard_bytes.map_or(Ok(usize::MAX), |max| {
+        if max < SHARD_BYTES_MULTIPLE {
+            Err(PyValueError::new_err(format!(
+                "max_shard_bytes must be at least {SHARD_BYTES_MULTIPLE}, got {max}"
+            )))
+        } else {
+            Ok(max / SHARD_BYTES_MULTIPLE * SHARD_BYTES_MULTIPLE)
+        }
+    })?;
+
+    let mut rejected = Vec::new();
+
+    let mut original_count = data_len.div_ceil(max_shard_bytes).max(1);
+    if original_count > 1 {
+        rejected.push(format!(
+            "original_count below {original_count}: shards over {max_shard_bytes} bytes"
+        ));
+    }
+    if let Some(overhead) = overhead {
+        let min_original_count = match tolerate_losses {
+            Some(recovery_count) => original_for(recovery_count, overhead),
+            None => original_within(original_count, overhead).ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "no supported original_count from {original_count} on has an overhead \
+                     of at most {overhead}; give tolerate_losses"
+                ))
+            })?,
+        };
+        if min_original_count > original_count {
+            original_count = min_original_count;
+            rejected.push(format!(
+                "original_count below {original_count}: overhead over {overhead}"
+            ));
+        }
+    }
+
+    let exact = data_len.div_ceil(original_count);
+    if exact % SHARD_BYTES_MULTIPLE != 0 {
+        rejected.push(format!(
+            "shard_bytes {exact}: shard sizes must be a multiple of {SHARD_BYTES_MULTIPLE}"
+        ));
+    }
+    let unaligned = round_up(exact, SHARD_BYTES_MULTIPLE);
+    let aligned = round_up(unaligned, ALIGNED_SHARD_BYTES);
+    let shard_bytes = if !prefer_aligned || aligned == unaligned {
+        unaligned
+    } else if aligned <= max_shard_bytes {
+        rejected.push(format!(
+            "shard_bytes {unaligned}: not a multiple of {ALIGNED_SHARD_BYTES}"
+        ));
+        aligned
+    } else {
+        rejected.push(format!(
+            "shard_bytes {aligned}: over {max_shard_bytes} bytes"
+        ));
+        unaligned
+    };
+
+    let recovery_count = tolerate_losses
+        .unwrap_or_else(|| recovery_for(original_count, overhead.expect("checked above")));
+    if !crate::supports(original_count, recovery_count) {
+        rejected.push(format!(
+            "{original_count} original with {recovery_count} recovery shards: unsupported"
+        ));
+        return Err(PyValueError::new_err(format!(
+            "no parameters fit, rejected {}; use larger shards or Striping",
+            rejected.join("; ")
+        )));
+    }
+
+    Ok(Plan {
+        original_count,
+        recovery_count,
+        shard_bytes,
+        padding: original_count * shard_bytes - data_len,
+        overhead: recovery_count as f64 / original_count as f64,
+        rejected,
+    })
+}
@@ -0,0 +1,44 @@
+//! `SplitMix64`, a small pseudo-random generator for reproducible sampling
+//! and test data. Its output is predictable, so it must not be used where
+//! that matters.
+
+/// `SplitMix64` with its state.
+pub(crate) struct Random(pub(crate) u64);
+
+impl Random {
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+impl Random {
+    /// Uniform in `0..n`, close enough for tests.
+    #[allow(clippy::cast_possible_truncation)]
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        self.next_u64() as usize % n
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next_u64().to_le_bytes()[0]).collect()
+    }
+
+    /// `0..n` in random order.
+    pub(crate) fn shuffled(&mut self, n: usize) -> Vec<usize> {
+        let mut positions: Vec<usize> = (0..n).collect();
+        for i in (1..n).rev() {
+            positions.swap(i, self.below(i + 1));
+        }
+        positions
+    }
+}
@@ -0,0 +1,163 @@
+//! Rebuilding lost shards of every kind, e.g. after replacing a failed disk.
+
+use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyBytes, PyDict, PyList};
+
+use reed_solomon_simd::{ReedSolomonDecoder, ReedSolomonEncoder};
+
+use std::collections::HashMap;
+
+use crate::buffer::Shard;
+use crate::error::Error;
+
+/// Restored shards by index, as `(original, recovery)`.
+type Restored<'py> = (Vec<(usize, &'py PyBytes)>, Vec<(usize, &'py PyBytes)>);
+
+/// Restores the original shards missing from `original` and, if
+/// `with_recovery` is set, the recovery shards missing from `recovery`.
+///
+/// The restored original shards are fed to the encoder straight from the
+/// decoder's working space, so nothing is copied in between.
+pub(crate) fn restore<'py, S: AsRef<[u8]> + Sync>(
+    py: Python<'py>,
+    original_count: usize,
+    recovery_count: usize,
+    original: &HashMap<usize, S>,
+    recovery: &HashMap<usize, S>,
+    with_recovery: bool,
+) -> PyResult<Restored<'py>> {
+    let Some(first) = original.values().chain(recovery.values()).next() else {
+        return Err(Error(reed_solomon_simd::Error::NotEnoughShards {
+            original_count,
+            original_received_count: 0,
+            recovery_received_count: 0,
+        })
+        .into());
+    };
+    let shard_bytes = first.as_ref().len();
+
+    let mut decoder = ReedSolomonDecoder::new(original_count, recovery_count, shard_bytes)
+        .map_err(Error::from)?;
+    let mut encoder = ReedSolomonEncoder::new(original_count, recovery_count, shard_bytes)
+        .map_err(Error::from)?;
+
+    let (decoder_result, encoder_result) = py
+        .allow_threads(|| {
+            for (&idx, shard) in original {
+                decoder.add_original_shard(idx, shard)?;
+            }
+            for (&idx, shard) in recovery {
+                decoder.add_recovery_shard(idx, shard)?;
+            }
+            let decoder_result = decoder.decode()?;
+
+            let encoder_result = if with_recovery && recovery.len() < recovery_count {
+                for idx in 0..original_count {
+                    let original_shard = original.get(&idx).map_or_else(
+                        || {
+                            decoder_result
+                                .restored_original(idx)
+                                .expect("original shard is either received or restored")
+                        },
+                        AsRef::as_ref,
+                    );
+                    encoder.add_original_shard(original_shard)?;
+                }
+                Some(encoder.encode()?)
+            } else {
+                None
+            };
+
+            Ok((decoder_result, encoder_result))
+        })
+        .map_err(|err: reed_solomon_simd::Error| Error(err))?;
+
+    let restored_original = decoder_result
+        .restored_original_iter()
+        .map(|(idx, shard)| (idx, PyBytes::new(py, shard)))
+        .collect();
+    let restored_recovery = encoder_result
+        .iter()
+        .flat_map(reed_solomon_simd::EncoderResult::recovery_iter)
+        .enumerate()
+        .filter(|(idx, _)| !recovery.contains_key(idx))
+        .map(|(idx, shard)| (idx, PyBytes::new(py, shard)))
+        .collect();
+
+    Ok((restored_original, restored_recovery))
+}
+
+/// Like `decode`, but restores the missing recovery shards as well. Returns
+/// the dicts `(original, recovery)` of restored shards by index.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+pub(crate) fn repair(
+    py: Python<'_>,
+    original_count: usize,
+    recovery_count: usize,
+    original: HashMap<usize, Shard>,
+    recovery: HashMap<usize, Shard>,
+) -> PyResult<(Py<PyDict>, Py<PyDict>)> {
+    let (restored_original, restored_recovery) = restore(
+        py,
+        original_count,
+        recovery_count,
+        &original,
+        &recovery,
+        true,
+    )?;
+    Ok((
+        restored_original.into_py_dict(py).into(),
+        restored_recovery.into_py_dict(py).into(),
+    ))
+}
+
+/// Fills in the lost shards of `shards`, a list of all `original_count`
+/// original shards followed by the recovery shards, with `None` in place of
+/// each lost shard. Lost recovery shards are only restored if `recovery` is
+/// set, otherwise they stay `None`.
+#[pyfunction]
+#[pyo3(signature = (shards, original_count, recovery=false))]
+pub(crate) fn reconstruct(
+    py: Python<'_>,
+    shards: &PyList,
+    original_count: usize,
+    recovery: bool,
+) -> PyResult<()> {
+    let recovery_count = shards.len().saturating_sub(original_count);
+    if !ReedSolomonDecoder::supports(original_count, recovery_count) {
+        return Err(Error(reed_solomon_simd::Error::UnsupportedShardCount {
+            original_count,
+            recovery_count,
+        })
+        .into());
+    }
+
+    let mut original_shards = HashMap::new();
+    let mut recovery_shards = HashMap::new();
+    for (idx, item) in shards.iter().enumerate() {
+        if let Some(shard) = item.extract::<Option<Shard>>()? {
+            if idx < original_count {
+                original_shards.insert(idx, shard);
+            } else {
+                recovery_shards.insert(idx - original_count, shard);
+            }
+        }
+    }
+
+    let (restored_original, restored_recovery) = restore(
+        py,
+        original_count,
+        recovery_count,
+        &original_shards,
+        &recovery_shards,
+        recovery,
+    )?;
+    for (idx, shard) in restored_original {
+        shards.set_item(idx, shard)?;
+    }
+    for (idx, shard) in restored_recovery {
+        shards.set_item(original_count + idx, shard)?;
+    }
+    Ok(())
+}
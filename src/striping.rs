@@ -0,0 +1,248 @@
+//! Codes over more shards than a single Reed-Solomon code supports, by
+//! splitting them into stripes which each are a code of their own.
+//!
+//! With `s` stripes, original shard `i` belongs to stripe `i % s` with local
+//! index `i / s`, and likewise for recovery shards. Interleaving the shards
+//! this way spreads a run of consecutive lost shards over all stripes.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+use reed_solomon_simd::{ReedSolomonDecoder, ReedSolomonEncoder};
+
+use std::collections::HashMap;
+
+use crate::buffer::Shard;
+use crate::error::Error;
+
+/// Relative error ignored when rounding up a shard count computed with a
+/// float ratio. `100.0 * 0.07` is slightly over 7, but means 7.
+const RATIO_TOLERANCE: f64 = 1e-9;
+
+/// `count` rounded up, ignoring an excess within `RATIO_TOLERANCE`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn ceil_count(count: f64) -> usize {
+    (count - count * RATIO_TOLERANCE).ceil() as usize
+}
+
+/// Recovery shards needed for `original_count` original shards with the
+/// given ratio of recovery to original shards.
+#[allow(clippy::cast_precision_loss)]
+fn recovery_total(original_count: usize, redundancy: f64) -> usize {
+    ceil_count(original_count as f64 * redundancy)
+}
+
+/// Number of shards of a kind in stripe `stripe` of `stripes`, when `total`
+/// are interleaved over them.
+fn share(total: usize, stripes: usize, stripe: usize) -> usize {
+    total / stripes + usize::from(stripe < total % stripes)
+}
+
+/// Splits `original_count` original shards with `redundancy` recovery shards
+/// per original shard into the fewest stripes which are each supported by a
+/// single code, and presents them as one code with a single index space.
+#[pyclass(module = "reed_solomon_leopard")]
+pub(crate) struct Striping {
+    original_count: usize,
+    recovery_count: usize,
+    stripes: usize,
+}
+
+#[pymethods]
+impl Striping {
+    #[new]
+    fn new(original_count: usize, redundancy: f64) -> PyResult<Self> {
+        if !(redundancy.is_finite() && redundancy > 0.0) {
+            return Err(PyValueError::new_err(format!(
+                "redundancy must be a positive number, got {redundancy}"
+            )));
+        }
+        let recovery_count = recovery_total(original_count, redundancy);
+
+        (1..=original_count.min(recovery_count))
+            .map(|stripes| Self {
+                original_count,
+                recovery_count,
+                stripes,
+            })
+            .find(|striping| {
+                // Stripes have at most three distinct shapes, starting at
+                // these stripes.
+                [
+                    0,
+                    original_count % striping.stripes,
+                    recovery_count % striping.stripes,
+                ]
+                .into_iter()
+                .all(|stripe| {
+                    let (original, recovery) = striping.counts(stripe);
+                    crate::supports(original, recovery)
+                })
+            })
+            .ok_or_else(|| {
+                Error(reed_solomon_simd::Error::UnsupportedShardCount {
+                    original_count,
+                    recovery_count,
+                })
+                .into()
+            })
+    }
+
+    #[getter]
+    fn original_count(&self) -> usize {
+        self.original_count
+    }
+
+    /// Total number of recovery shards, `ceil(original_count * redundancy)`.
+    #[getter]
+    fn recovery_count(&self) -> usize {
+        self.recovery_count
+    }
+
+    /// `(original_count, recovery_count)` of each stripe.
+    #[getter]
+    fn stripes(&self) -> Vec<(usize, usize)> {
+        (0..self.stripes)
+            .map(|stripe| self.counts(stripe))
+            .collect()
+    }
+
+    /// Returns `(stripe, local_index)` of original shard `index`, or of
+    /// recovery shard `index` if `is_recovery` is set.
+    #[pyo3(signature = (index, is_recovery=false))]
+    fn locate(&self, index: usize, is_recovery: bool) -> PyResult<(usize, usize)> {
+        self.check_index(index, is_recovery)?;
+        Ok((index % self.stripes, index / self.stripes))
+    }
+
+    /// Returns all `recovery_count` recovery shards for the
+    /// `original_count` shards in `original`.
+    #[allow(clippy::needless_pass_by_value)]
+    fn encode<'py>(&self, py: Python<'py>, original: Vec<Shard>) -> PyResult<Vec<&'py PyBytes>> {
+        if original.len() != self.original_count {
+            return Err(PyValueError::new_err(format!(
+                "expected {} original shards, got {}",
+                self.original_count,
+                original.len()
+            )));
+        }
+        let shard_bytes = original[0].as_ref().len();
+        let mut encoder = ReedSolomonEncoder::new(1, 1, shard_bytes).map_err(Error::from)?;
+
+        let mut recovery = vec![None; self.recovery_count];
+        for stripe in 0..self.stripes {
+            let (original_count, recovery_count) = self.counts(stripe);
+            let encoder_result = py
+                .allow_threads(|| {
+                    encoder.reset(original_count, recovery_count, shard_bytes)?;
+                    for shard in original.iter().skip(stripe).step_by(self.stripes) {
+                        encoder.add_original_shard(shard)?;
+                    }
+                    encoder.encode()
+                })
+                .map_err(Error::from)?;
+            for (idx, shard) in encoder_result.recovery_iter().enumerate() {
+                recovery[idx * self.stripes + stripe] = Some(PyBytes::new(py, shard));
+            }
+        }
+
+        Ok(recovery
+            .into_iter()
+            .map(|shard| shard.expect("every recovery shard is in a stripe"))
+            .collect())
+    }
+
+    /// Like `decode`, with the indices of all stripes combined: returns a
+    /// dict with the original shards missing from `original` by index. Each
+    /// stripe needs as many shards as it has original shards.
+    #[allow(clippy::needless_pass_by_value)]
+    fn decode(
+        &self,
+        py: Python<'_>,
+        original: HashMap<usize, Shard>,
+        recovery: HashMap<usize, Shard>,
+    ) -> PyResult<Py<PyDict>> {
+        for &index in original.keys() {
+            self.check_index(index, false)?;
+        }
+        for &index in recovery.keys() {
+            self.check_index(index, true)?;
+        }
+
+        let restored = PyDict::new(py);
+        let Some(first) = original.values().chain(recovery.values()).next() else {
+            return Err(Error(reed_solomon_simd::Error::NotEnoughShards {
+                original_count: self.original_count,
+                original_received_count: 0,
+                recovery_received_count: 0,
+            })
+            .into());
+        };
+        let shard_bytes = first.as_ref().len();
+        let mut decoder = ReedSolomonDecoder::new(1, 1, shard_bytes).map_err(Error::from)?;
+
+        let mut local = vec![(Vec::new(), Vec::new()); self.stripes];
+        for (&index, shard) in &original {
+            local[index % self.stripes]
+                .0
+                .push((index / self.stripes, shard));
+        }
+        for (&index, shard) in &recovery {
+            local[index % self.stripes]
+                .1
+                .push((index / self.stripes, shard));
+        }
+
+        for (stripe, (local_original, local_recovery)) in local.iter().enumerate() {
+            let (original_count, recovery_count) = self.counts(stripe);
+            if local_original.len() == original_count {
+                continue;
+            }
+
+            let decoder_result = py
+                .allow_threads(|| {
+                    decoder.reset(original_count, recovery_count, shard_bytes)?;
+                    for &(idx, shard) in local_original {
+                        decoder.add_original_shard(idx, shard)?;
+                    }
+                    for &(idx, shard) in local_recovery {
+                        decoder.add_recovery_shard(idx, shard)?;
+                    }
+                    decoder.decode()
+                })
+                .map_err(Error::from)?;
+            for (idx, shard) in decoder_result.restored_original_iter() {
+                restored.set_item(idx * self.stripes + stripe, PyBytes::new(py, shard))?;
+            }
+        }
+
+        Ok(restored.into())
+    }
+}
+
+impl Striping {
+    /// `(original_count, recovery_count)` of stripe `stripe`.
+    fn counts(&self, stripe: usize) -> (usize, usize) {
+        (
+            share(self.original_count, self.stripes, stripe),
+            share(self.recovery_count, self.stripes, stripe),
+        )
+    }
+
+    fn check_index(&self, index: usize, is_recovery: bool) -> Result<(), Error> {
+        if is_recovery && index >= self.recovery_count {
+            return Err(Error(reed_solomon_simd::Error::InvalidRecoveryShardIndex {
+                recovery_count: self.recovery_count,
+                index,
+            }));
+        }
+        if !is_recovery && index >= self.original_count {
+            return Err(Error(reed_solomon_simd::Error::InvalidOriginalShardIndex {
+                original_count: self.original_count,
+                index,
+            }));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,479 @@
+//! Volumes: data of any size laid out as many stripes in a directory of
+//! shard files, one per shard index and named like those of `encode_file`.
+//! Every shard file describes the whole volume, so any `original_count` of
+//! them are enough to read it. A shard file holds:
+//!
+//! | size                    | field                                     |
+//! | ----------------------- | ----------------------------------------- |
+//! | 32                      | header of `pack_shard`, see below         |
+//! | `stripes * shard_bytes` | the shard of every stripe in turn         |
+//! | `stripes * 8`           | stripe table: CRC32C of each shard        |
+//! | 32                      | footer, see below                         |
+//!
+//! The header's payload length is `shard_bytes`, its length that of the
+//! data. Footer layout, all integers little-endian:
+//!
+//! | offset | size | field                        |
+//! | ------ | ---- | ---------------------------- |
+//! | 0      | 8    | offset of the stripe table   |
+//! | 8      | 8    | stripe count                 |
+//! | 16     | 8    | CRC32C of the stripe table   |
+//! | 24     | 4    | magic `b"RSLV"`              |
+//! | 28     | 4    | reserved, zero               |
+//!
+//! Reads check each shard against the stripe table. Stripes missing any of
+//! the requested original shards are decoded from the intact shards, all
+//! other stripes are read directly.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use reed_solomon_simd::{ReedSolomonDecoder, ReedSolomonEncoder};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::buffer::Shard;
+use crate::checksum::Algorithm;
+use crate::error::Error;
+use crate::file::{self, FileError, FilePool};
+use crate::frame::{Header, HEADER_BYTES};
+
+const FOOTER_MAGIC: &[u8; 4] = b"RSLV";
+const FOOTER_BYTES: usize = 32;
+const CHECKSUM: Algorithm = Algorithm::Crc32c;
+
+/// Offset of the stripe table in a shard file.
+fn table_offset(stripes: u64, shard_bytes: usize) -> u64 {
+    HEADER_BYTES as u64 + stripes * shard_bytes as u64
+}
+
+fn footer(stripes: u64, shard_bytes: usize, table: &[u8]) -> [u8; FOOTER_BYTES] {
+    let mut footer = [0; FOOTER_BYTES];
+    footer[0..8].copy_from_slice(&table_offset(stripes, shard_bytes).to_le_bytes());
+    footer[8..16].copy_from_slice(&stripes.to_le_bytes());
+    footer[16..24].copy_from_slice(&CHECKSUM.checksum(table).to_le_bytes());
+    footer[24..28].copy_from_slice(FOOTER_MAGIC);
+    footer
+}
+
+/// Writes `data` as a volume into `dir`, with the parameters of `stripe`.
+fn write(dir: &Path, data: &[u8], stripe: &Header) -> Result<(), FileError> {
+    let original_count = stripe.original_count as usize;
+    let recovery_count = stripe.recovery_count as usize;
+    let shard_bytes = stripe.payload_bytes as usize;
+    let mut encoder = ReedSolomonEncoder::new(original_count, recovery_count, shard_bytes)?;
+    let stripe_bytes = original_count * shard_bytes;
+    let stripes = data.len().div_ceil(stripe_bytes) as u64;
+
+    fs::create_dir_all(dir).map_err(FileError::io(dir))?;
+    let paths: Vec<PathBuf> = (0..original_count + recovery_count)
+        .map(|index| file::shard_path(dir, index))
+        .collect();
+    let flush = |index: usize, mut output: BufWriter<File>| {
+        output.flush().map_err(FileError::io(&paths[index]))
+    };
+    let append = |path: &PathBuf| {
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map(BufWriter::new)
+            .map_err(FileError::io(path))
+    };
+    let mut outputs = FilePool::new(paths.len());
+    for (index, path) in paths.iter().enumerate() {
+        let output = outputs.get(
+            index,
+            || {
+                File::create(path)
+                    .map(BufWriter::new)
+                    .map_err(FileError::io(path))
+            },
+            flush,
+        )?;
+
+        let is_recovery = index >= original_count;
+        let header = Header {
+            index: u32::try_from(if is_recovery {
+                index - original_count
+            } else {
+                index
+            })
+            .expect("index is below a u32 count"),
+            is_recovery,
+            ..*stripe
+        };
+        let mut header_bytes = [0; HEADER_BYTES];
+        header.write(&mut header_bytes);
+        output
+            .write_all(&header_bytes)
+            .map_err(FileError::io(path))?;
+    }
+
+    let mut tables = vec![Vec::new(); paths.len()];
+    let mut write_shard = |index: usize, shard: &[u8]| {
+        let path = &paths[index];
+        let output = outputs.get(index, || append(path), flush)?;
+        tables[index].extend(CHECKSUM.checksum(shard).to_le_bytes());
+        output.write_all(shard).map_err(FileError::io(path))
+    };
+
+    let mut padded = vec![0; stripe_bytes];
+    for stripe in data.chunks(stripe_bytes) {
+        let stripe = if stripe.len() == stripe_bytes {
+            stripe
+        } else {
+            padded[..stripe.len()].copy_from_slice(stripe);
+            padded[stripe.len()..].fill(0);
+            &padded
+        };
+        for (index, shard) in stripe.chunks(shard_bytes).enumerate() {
+            encoder.add_original_shard(shard)?;
+            write_shard(index, shard)?;
+        }
+        let encoder_result = encoder.encode()?;
+        for (idx, shard) in encoder_result.recovery_iter().enumerate() {
+            write_shard(original_count + idx, shard)?;
+        }
+    }
+
+    for (index, table) in tables.iter().enumerate() {
+        let path = &paths[index];
+        let output = outputs.get(index, || append(path), flush)?;
+        output
+            .write_all(table)
+            .and_then(|()| output.write_all(&footer(stripes, shard_bytes, table)))
+            .map_err(FileError::io(path))?;
+    }
+    outputs.close_all(flush)?;
+    Ok(())
+}
+
+/// An intact shard file of an open volume.
+struct ShardFile {
+    path: PathBuf,
+    /// Checksum of the shard of each stripe.
+    table: Vec<u64>,
+}
+
+impl ShardFile {
+    /// Opens the shard file at `path`, checking its header and footer.
+    /// Returns `None` if it's missing or damaged.
+    fn open(path: &Path) -> Option<(Header, Self)> {
+        let mut file = File::open(path).ok()?;
+        let mut header_bytes = [0; HEADER_BYTES];
+        file.read_exact(&mut header_bytes).ok()?;
+        let header = Header::read(&header_bytes).ok()?;
+
+        let stripe_bytes = u64::from(header.original_count) * u64::from(header.payload_bytes);
+        let stripes =
+            header.length.checked_div(stripe_bytes)? + u64::from(header.length % stripe_bytes != 0);
+        let table_bytes = usize::try_from(stripes).ok()?.checked_mul(8)?;
+
+        let table_offset = table_offset(stripes, header.payload_bytes as usize);
+        let file_bytes = table_offset + table_bytes as u64 + FOOTER_BYTES as u64;
+        if file.metadata().ok()?.len() != file_bytes {
+            return None;
+        }
+        let mut table = vec![0; table_bytes];
+        let mut footer_bytes = [0; FOOTER_BYTES];
+        file.seek(SeekFrom::Start(table_offset)).ok()?;
+        file.read_exact(&mut table).ok()?;
+        file.read_exact(&mut footer_bytes).ok()?;
+        if footer_bytes != footer(stripes, header.payload_bytes as usize, &table) {
+            return None;
+        }
+
+        let table = table
+            .chunks(8)
+            .map(|checksum| u64::from_le_bytes(checksum.try_into().expect("8 bytes")))
+            .collect();
+        Some((
+            header,
+            Self {
+                path: path.to_owned(),
+                table,
+            },
+        ))
+    }
+
+    /// Reads the shard of `stripe` from `file`, this shard file opened,
+    /// into `shard`.
+    fn read(&self, file: &mut File, stripe: u64, shard: &mut [u8]) -> bool {
+        let offset = table_offset(stripe, shard.len());
+        file.seek(SeekFrom::Start(offset)).is_ok()
+            && file.read_exact(shard).is_ok()
+            && usize::try_from(stripe)
+                .ok()
+                .and_then(|stripe| self.table.get(stripe))
+                == Some(&CHECKSUM.checksum(shard))
+    }
+}
+
+/// Opens the volume in `directory` for random-access reads. Shard files
+/// which are missing or damaged are skipped.
+#[pyclass(module = "reed_solomon_leopard")]
+pub(crate) struct Volume {
+    original_count: usize,
+    recovery_count: usize,
+    shard_bytes: usize,
+    length: u64,
+    files: Vec<Option<ShardFile>>,
+    /// The open shard files, at most `MAX_OPEN_FILES` of them.
+    handles: FilePool<File>,
+    decoder: ReedSolomonDecoder,
+}
+
+#[pymethods]
+impl Volume {
+    #[allow(clippy::needless_pass_by_value)]
+    #[new]
+    fn new(directory: PathBuf) -> PyResult<Self> {
+        let entries = fs::read_dir(&directory).map_err(FileError::io(&directory))?;
+        let mut indices = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(FileError::io(&directory))?;
+            if let Some(index) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_suffix(".shard"))
+                .and_then(|index| index.parse::<usize>().ok())
+            {
+                indices.push(index);
+            }
+        }
+        indices.sort_unstable();
+
+        // The first intact shard file decides the volume's parameters.
+        let mut stripe = None;
+        let mut opened = Vec::new();
+        for index in indices {
+            let Some((header, shard_file)) = ShardFile::open(&file::shard_path(&directory, index))
+            else {
+                continue;
+            };
+            let stripe = *stripe.get_or_insert(header);
+            let position = header.index as usize
+                + if header.is_recovery {
+                    header.original_count as usize
+                } else {
+                    0
+                };
+            if header.same_stripe(&stripe) && position == index {
+                opened.push((index, shard_file));
+            }
+        }
+        let Some(stripe) = stripe else {
+            return Err(PyValueError::new_err(format!(
+                "no intact volume shard files in {}",
+                directory.display()
+            )));
+        };
+
+        let original_count = stripe.original_count as usize;
+        let recovery_count = stripe.recovery_count as usize;
+        let shard_bytes = stripe.payload_bytes as usize;
+        let mut files: Vec<Option<ShardFile>> =
+            (0..original_count + recovery_count).map(|_| None).collect();
+        for (index, shard_file) in opened {
+            if let Some(slot) = files.get_mut(index) {
+                *slot = Some(shard_file);
+            }
+        }
+
+        Ok(Self {
+            original_count,
+            recovery_count,
+            shard_bytes,
+            length: stripe.length,
+            handles: FilePool::new(files.len()),
+            files,
+            decoder: ReedSolomonDecoder::new(original_count, recovery_count, shard_bytes)
+                .map_err(Error::from)?,
+        })
+    }
+
+    #[getter]
+    fn original_count(&self) -> usize {
+        self.original_count
+    }
+
+    #[getter]
+    fn recovery_count(&self) -> usize {
+        self.recovery_count
+    }
+
+    #[getter]
+    fn shard_bytes(&self) -> usize {
+        self.shard_bytes
+    }
+
+    /// Length of the data in bytes.
+    #[getter]
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    #[getter]
+    fn stripes(&self) -> u64 {
+        self.length.div_ceil(self.stripe_bytes())
+    }
+
+    /// Shard indices, original shards first, whose file is missing or
+    /// damaged.
+    #[getter]
+    fn missing(&self) -> Vec<usize> {
+        (0..self.files.len())
+            .filter(|&index| self.files[index].is_none())
+            .collect()
+    }
+
+    /// Returns `length` bytes of the data starting at `offset`, decoding
+    /// only the stripes with lost shards in that range.
+    fn read<'py>(&mut self, py: Python<'py>, offset: u64, length: usize) -> PyResult<&'py PyBytes> {
+        if offset
+            .checked_add(length as u64)
+            .is_none_or(|end| end > self.length)
+        {
+            return Err(PyValueError::new_err(format!(
+                "range of {length} bytes at offset {offset} is outside of the {} bytes of data",
+                self.length
+            )));
+        }
+        PyBytes::new_with(py, length, |out| {
+            py.allow_threads(|| self.read_into(offset, out))
+        })
+    }
+}
+
+impl Volume {
+    fn stripe_bytes(&self) -> u64 {
+        (self.original_count * self.shard_bytes) as u64
+    }
+
+    fn read_into(&mut self, offset: u64, mut out: &mut [u8]) -> PyResult<()> {
+        let mut position = offset;
+        while !out.is_empty() {
+            let stripe = position / self.stripe_bytes();
+            let within = usize::try_from(position % self.stripe_bytes()).expect("within a stripe");
+            let take = out
+                .len()
+                .min(self.original_count * self.shard_bytes - within);
+            let (part, rest) = out.split_at_mut(take);
+            self.read_stripe(stripe, within, part)?;
+            position += take as u64;
+            out = rest;
+        }
+        Ok(())
+    }
+
+    /// Reads shard `index` of `stripe` into `shard`. Returns `false` if its
+    /// file is missing or it can't be read or is damaged.
+    fn read_shard(&mut self, index: usize, stripe: u64, shard: &mut [u8]) -> bool {
+        let Some(shard_file) = &self.files[index] else {
+            return false;
+        };
+        self.handles
+            .get(index, || File::open(&shard_file.path), |_, _| Ok(()))
+            .is_ok_and(|file| shard_file.read(file, stripe, shard))
+    }
+
+    /// Reads `out.len()` bytes at `within` of `stripe`.
+    fn read_stripe(&mut self, stripe: u64, within: usize, out: &mut [u8]) -> PyResult<()> {
+        let shard_bytes = self.shard_bytes;
+        let first = within / shard_bytes;
+        let last = (within + out.len() - 1) / shard_bytes;
+        let copy = |idx: usize, shard: &[u8], out: &mut [u8]| {
+            let start = (idx * shard_bytes).max(within);
+            let end = ((idx + 1) * shard_bytes).min(within + out.len());
+            out[start - within..end - within]
+                .copy_from_slice(&shard[start - idx * shard_bytes..end - idx * shard_bytes]);
+        };
+
+        let mut shard = vec![0; shard_bytes];
+        let mut intact = true;
+        for idx in first..=last {
+            if self.read_shard(idx, stripe, &mut shard) {
+                copy(idx, &shard, out);
+            } else {
+                intact = false;
+                break;
+            }
+        }
+        if intact {
+            return Ok(());
+        }
+
+        let mut received = Vec::new();
+        for index in 0..self.files.len() {
+            if received.len() == self.original_count {
+                break;
+            }
+            if self.read_shard(index, stripe, &mut shard) {
+                received.push((index, shard.clone()));
+            }
+        }
+        // Fail before adding any shards, which would stay in the decoder.
+        if received.len() < self.original_count {
+            let original_received_count = received
+                .iter()
+                .filter(|(index, _)| *index < self.original_count)
+                .count();
+            return Err(Error(reed_solomon_simd::Error::NotEnoughShards {
+                original_count: self.original_count,
+                original_received_count,
+                recovery_received_count: received.len() - original_received_count,
+            })
+            .into());
+        }
+        for (index, shard) in &received {
+            if *index < self.original_count {
+                self.decoder.add_original_shard(*index, shard)
+            } else {
+                self.decoder
+                    .add_recovery_shard(index - self.original_count, shard)
+            }
+            .map_err(Error::from)?;
+        }
+        let decoder_result = self.decoder.decode().map_err(Error::from)?;
+
+        for idx in first..=last {
+            let shard = received
+                .iter()
+                .find(|(index, _)| *index == idx)
+                .map(|(_, shard)| shard.as_slice())
+                .or_else(|| decoder_result.restored_original(idx))
+                .expect("original shard is either received or restored");
+            copy(idx, shard, out);
+        }
+        Ok(())
+    }
+}
+
+/// Writes `data` as a volume of stripes of `original_count` shards of
+/// `shard_bytes` bytes plus `recovery_count` recovery shards each, into one
+/// file per shard index in `directory`, and opens it.
+#[allow(clippy::needless_pass_by_value)]
+#[pyfunction]
+pub(crate) fn write_volume(
+    py: Python<'_>,
+    directory: PathBuf,
+    data: Shard,
+    original_count: usize,
+    recovery_count: usize,
+    shard_bytes: usize,
+) -> PyResult<Volume> {
+    let data = data.as_ref();
+    let stripe = Header::new(
+        original_count,
+        recovery_count,
+        0,
+        false,
+        shard_bytes,
+        data.len() as u64,
+    )?;
+    py.allow_threads(|| write(&directory, data, &stripe))?;
+    Volume::new(directory)
+}
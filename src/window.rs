@@ -0,0 +1,544 @@
+//! Sliding-window forward error correction for packet streams, for when the
+//! latency of waiting for a whole block is too much, e.g. live media.
+//!
+//! The sender sends each payload right away as a source packet, and every
+//! `repair_interval` payloads also `repair_count` repair packets, which each
+//! protect the last `window` source packets. A repair packet is a random
+//! linear combination over GF(2^16) of the source shards it covers, with
+//! coefficients derived from its repair sequence number, so the receiver
+//! restores a lost payload as soon as it has enough repair packets covering
+//! it, without waiting for the end of a block. Source shards are framed as in
+//! `fec`: the payload length as a little-endian `u16`, the payload and zero
+//! padding to an even size, which is how the receiver trims restored ones.
+//!
+//! Packet header, all integers little-endian:
+//!
+//! | offset | size | field                                                  |
+//! | ------ | ---- | ------------------------------------------------------ |
+//! | 0      | 4    | sequence number (source) or first one covered (repair) |
+//! | 4      | 2    | source packets covered, 0 in source packets            |
+//! | 6      | 2    | body length                                            |
+//! | 8      | 4    | repair sequence number, in repair packets only         |
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use reed_solomon_simd::engine::{GfElement, GF_MODULUS};
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::buffer::Shard;
+use crate::fec::{read_shard, write_shard, LENGTH_BYTES, MAX_PAYLOAD_BYTES};
+use crate::gf;
+use crate::random::Random;
+
+const SOURCE_HEADER_BYTES: usize = 8;
+const REPAIR_HEADER_BYTES: usize = 12;
+
+enum Packet<'a> {
+    Source {
+        seq: u32,
+        payload: &'a [u8],
+    },
+    Repair {
+        first: u32,
+        count: u16,
+        repair_seq: u32,
+        body: &'a [u8],
+    },
+}
+
+impl<'a> Packet<'a> {
+    fn write<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        let (seq, count, header_bytes, body) = match *self {
+            Self::Source { seq, payload } => (seq, 0, SOURCE_HEADER_BYTES, payload),
+            Self::Repair {
+                first, count, body, ..
+            } => (first, count, REPAIR_HEADER_BYTES, body),
+        };
+        let length = u16::try_from(body.len()).expect("body length is checked");
+        PyBytes::new_with(py, header_bytes + body.len(), |packet| {
+            packet[0..4].copy_from_slice(&seq.to_le_bytes());
+            packet[4..6].copy_from_slice(&count.to_le_bytes());
+            packet[6..8].copy_from_slice(&length.to_le_bytes());
+            if let Self::Repair { repair_seq, .. } = *self {
+                packet[8..12].copy_from_slice(&repair_seq.to_le_bytes());
+            }
+            packet[header_bytes..].copy_from_slice(body);
+            Ok(())
+        })
+        .expect("filling a new packet can't fail")
+    }
+
+    fn parse(packet: &'a [u8]) -> PyResult<Self> {
+        if packet.len() < SOURCE_HEADER_BYTES {
+            return Err(PyValueError::new_err(format!(
+                "window FEC packet too short: {} bytes, header alone is {SOURCE_HEADER_BYTES}",
+                packet.len()
+            )));
+        }
+        let u16_at = |offset: usize| {
+            u16::from_le_bytes(packet[offset..offset + 2].try_into().expect("2 bytes"))
+        };
+        let seq = u32::from_le_bytes(packet[0..4].try_into().expect("4 bytes"));
+        let count = u16_at(4);
+        let length = usize::from(u16_at(6));
+
+        let header_bytes = if count == 0 {
+            SOURCE_HEADER_BYTES
+        } else {
+            REPAIR_HEADER_BYTES
+        };
+        let body = packet.get(header_bytes..).unwrap_or_default();
+        if packet.len() < header_bytes || body.len() != length {
+            return Err(PyValueError::new_err(format!(
+                "window FEC packet is {} bytes, header says {}",
+                packet.len(),
+                header_bytes + length
+            )));
+        }
+        if count == 0 {
+            return Ok(Self::Source { seq, payload: body });
+        }
+        if length % 2 != 0 {
+            return Err(PyValueError::new_err(format!(
+                "window FEC repair packet body is {length} bytes, must be even"
+            )));
+        }
+        Ok(Self::Repair {
+            first: seq,
+            count,
+            repair_seq: u32::from_le_bytes(packet[8..12].try_into().expect("4 bytes")),
+            body,
+        })
+    }
+}
+
+/// Coefficient of source packet `seq` in repair packet `repair_seq`, never
+/// zero so that every repair packet depends on all source packets it covers.
+fn coefficient(repair_seq: u32, seq: u32) -> GfElement {
+    let z = Random(u64::from(repair_seq) << 32 | u64::from(seq)).next_u64();
+    GfElement::try_from(z % u64::from(GF_MODULUS)).expect("below GF_MODULUS") + 1
+}
+
+/// The source shard of `payload`.
+fn shard(payload: &[u8]) -> Vec<u8> {
+    let mut shard = vec![0; (LENGTH_BYTES + payload.len()).next_multiple_of(2)];
+    write_shard(payload, &mut shard);
+    shard
+}
+
+fn check_window(window: usize) -> PyResult<u16> {
+    u16::try_from(window)
+        .ok()
+        .filter(|&window| window > 0)
+        .ok_or_else(|| {
+            PyValueError::new_err(format!(
+                "window must be between 1 and {}, got {window}",
+                u16::MAX
+            ))
+        })
+}
+
+/// Whether sequence number `seq` comes before `other`.
+fn is_behind(seq: u32, other: u32) -> bool {
+    let ahead = other.wrapping_sub(seq);
+    ahead != 0 && ahead <= u32::MAX / 2
+}
+
+/// Sends a stream of payloads with repair packets over a sliding window.
+#[pyclass(module = "reed_solomon_leopard")]
+pub(crate) struct SlidingWindowSender {
+    window: u16,
+    repair_interval: usize,
+    repair_count: usize,
+    /// Sequence number of the next source packet.
+    seq: u32,
+    repair_seq: u32,
+    /// Shards of the last `window` source packets.
+    history: VecDeque<Vec<u8>>,
+    /// Source packets since the last repair packets.
+    unrepaired: usize,
+}
+
+#[pymethods]
+impl SlidingWindowSender {
+    /// Repair packets protect the last `window` source packets, and
+    /// `repair_count` of them are sent after every `repair_interval` source
+    /// packets. Restoring lost source packets takes time cubic in the number
+    /// lost within a window, so keep `window` in the hundreds at most.
+    #[new]
+    #[pyo3(signature = (window, repair_interval=1, repair_count=1))]
+    fn new(window: usize, repair_interval: usize, repair_count: usize) -> PyResult<Self> {
+        let window = check_window(window)?;
+        if repair_interval == 0 {
+            return Err(PyValueError::new_err("repair_interval must be at least 1"));
+        }
+        Ok(Self {
+            window,
+            repair_interval,
+            repair_count,
+            seq: 0,
+            repair_seq: 0,
+            history: VecDeque::new(),
+            unrepaired: 0,
+        })
+    }
+
+    /// Sequence number of the next source packet.
+    #[getter]
+    fn seq(&self) -> u32 {
+        self.seq
+    }
+
+    /// Returns the packets to send for `payload`: its source packet and, if
+    /// it completes a repair interval, the repair packets.
+    #[allow(clippy::needless_pass_by_value)]
+    fn send<'py>(&mut self, py: Python<'py>, payload: Shard) -> PyResult<Vec<&'py PyBytes>> {
+        let payload = payload.as_ref();
+        if payload.len() > MAX_PAYLOAD_BYTES {
+            return Err(PyValueError::new_err(format!(
+                "payload of {} bytes is larger than {MAX_PAYLOAD_BYTES}",
+                payload.len()
+            )));
+        }
+
+        let mut packets = vec![Packet::Source {
+            seq: self.seq,
+            payload,
+        }
+        .write(py)];
+        if self.history.len() == usize::from(self.window) {
+            self.history.pop_front();
+        }
+        self.history.push_back(shard(payload));
+        self.seq = self.seq.wrapping_add(1);
+        self.unrepaired += 1;
+        if self.unrepaired == self.repair_interval {
+            packets.extend(self.flush(py));
+        }
+        Ok(packets)
+    }
+
+    /// Returns `repair_count` repair packets over the current window right
+    /// away, e.g. at the end of a video frame or of the stream. Does nothing
+    /// if no payloads were sent since the last repair packets.
+    fn flush<'py>(&mut self, py: Python<'py>) -> Vec<&'py PyBytes> {
+        if self.unrepaired == 0 {
+            return Vec::new();
+        }
+        self.unrepaired = 0;
+
+        let count = u16::try_from(self.history.len()).expect("at most window");
+        let first = self.seq.wrapping_sub(u32::from(count));
+        let shard_bytes = self.history.iter().map(Vec::len).max().unwrap_or(0);
+        let history = &self.history;
+        let repair_seqs: Vec<u32> = (0..self.repair_count)
+            .map(|_| {
+                let repair_seq = self.repair_seq;
+                self.repair_seq = self.repair_seq.wrapping_add(1);
+                repair_seq
+            })
+            .collect();
+
+        let bodies = py.allow_threads(|| {
+            repair_seqs
+                .iter()
+                .map(|&repair_seq| {
+                    let mut body = vec![0; shard_bytes];
+                    for (shard, seq) in history.iter().zip(0..) {
+                        let seq = first.wrapping_add(seq);
+                        gf::mul_add(&mut body, shard, coefficient(repair_seq, seq));
+                    }
+                    body
+                })
+                .collect::<Vec<_>>()
+        });
+        repair_seqs
+            .into_iter()
+            .zip(&bodies)
+            .map(|(repair_seq, body)| {
+                Packet::Repair {
+                    first,
+                    count,
+                    repair_seq,
+                    body,
+                }
+                .write(py)
+            })
+            .collect()
+    }
+}
+
+/// A repair packet minus the known source shards: the sum of `terms`, each a
+/// coefficient times an unknown source shard, is `data`.
+struct Equation {
+    terms: BTreeMap<u32, GfElement>,
+    data: Vec<u8>,
+}
+
+impl Equation {
+    fn coefficient(&self, seq: u32) -> GfElement {
+        self.terms.get(&seq).copied().unwrap_or(0)
+    }
+
+    /// Adds `factor` times `other`.
+    fn mul_add(&mut self, other: &Self, factor: GfElement) {
+        for (&seq, &coefficient) in &other.terms {
+            let term = self.terms.entry(seq).or_insert(0);
+            *term ^= gf::mul(factor, coefficient);
+            if *term == 0 {
+                self.terms.remove(&seq);
+            }
+        }
+        if self.data.len() < other.data.len() {
+            self.data.resize(other.data.len(), 0);
+        }
+        gf::mul_add(&mut self.data, &other.data, factor);
+    }
+
+    /// Substitutes the known source shard of `seq`.
+    fn substitute(&mut self, seq: u32, shard: &[u8]) {
+        if let Some(coefficient) = self.terms.remove(&seq) {
+            if self.data.len() < shard.len() {
+                self.data.resize(shard.len(), 0);
+            }
+            gf::mul_add(&mut self.data, shard, coefficient);
+        }
+    }
+}
+
+/// Brings `equations` into reduced row echelon form, dropping redundant
+/// ones, and removes and returns those which determine a source shard.
+fn solve(equations: &mut Vec<Equation>) -> Vec<(u32, Vec<u8>)> {
+    let unknowns: Vec<u32> = equations
+        .iter()
+        .flat_map(|equation| equation.terms.keys().copied())
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let mut pivots = 0;
+    for seq in unknowns {
+        let Some(row) = (pivots..equations.len()).find(|&row| equations[row].coefficient(seq) != 0)
+        else {
+            continue;
+        };
+        equations.swap(pivots, row);
+        let mut pivot = std::mem::replace(
+            &mut equations[pivots],
+            Equation {
+                terms: BTreeMap::new(),
+                data: Vec::new(),
+            },
+        );
+        let factor = gf::inv(pivot.coefficient(seq));
+        for coefficient in pivot.terms.values_mut() {
+            *coefficient = gf::mul(factor, *coefficient);
+        }
+        gf::scale(&mut pivot.data, factor);
+        for equation in equations.iter_mut() {
+            let coefficient = equation.coefficient(seq);
+            if coefficient != 0 {
+                equation.mul_add(&pivot, coefficient);
+            }
+        }
+        equations[pivots] = pivot;
+        pivots += 1;
+    }
+    equations.truncate(pivots);
+
+    let mut solved = Vec::new();
+    equations.retain_mut(|equation| {
+        if equation.terms.len() != 1 {
+            return true;
+        }
+        let (&seq, _) = equation.terms.first_key_value().expect("one term");
+        solved.push((seq, std::mem::take(&mut equation.data)));
+        false
+    });
+    solved
+}
+
+/// Restores and orders the payloads from the packets of a
+/// `SlidingWindowSender`.
+#[pyclass(module = "reed_solomon_leopard")]
+pub(crate) struct SlidingWindowReceiver {
+    window: u16,
+    /// Sequence number of the next payload to release, once the first packet
+    /// arrived.
+    next_seq: Option<u32>,
+    /// Newest sequence number a packet was sent after.
+    newest: u32,
+    /// Shards of received and restored source packets, kept for a window
+    /// after their release to substitute into late repair packets.
+    sources: HashMap<u32, Vec<u8>>,
+    equations: Vec<Equation>,
+    lost: usize,
+}
+
+#[pymethods]
+impl SlidingWindowReceiver {
+    /// `window` must be that of the sender. A missing payload is given up on
+    /// once a packet arrives which was sent after the sender's window moved
+    /// past it.
+    #[new]
+    fn new(window: usize) -> PyResult<Self> {
+        Ok(Self {
+            window: check_window(window)?,
+            next_seq: None,
+            newest: 0,
+            sources: HashMap::new(),
+            equations: Vec::new(),
+            lost: 0,
+        })
+    }
+
+    /// Number of payloads given up on so far.
+    #[getter]
+    fn lost(&self) -> usize {
+        self.lost
+    }
+
+    /// Handles a received packet and returns the payloads which are now
+    /// released, in the order they were sent. Payloads are released as soon
+    /// as all before them are, restoring lost ones once enough repair packets
+    /// covering them arrived. The first packet to arrive starts the stream,
+    /// so packets sent before it are ignored.
+    #[allow(clippy::needless_pass_by_value)]
+    fn receive<'py>(&mut self, py: Python<'py>, packet: Shard) -> PyResult<Vec<&'py PyBytes>> {
+        let packet = Packet::parse(packet.as_ref())?;
+        if let Packet::Repair { count, .. } = packet {
+            if count > self.window {
+                return Err(PyValueError::new_err(format!(
+                    "window FEC repair packet covers {count} source packets, window is {}",
+                    self.window
+                )));
+            }
+        }
+        let (first, newest) = match packet {
+            Packet::Source { seq, .. } => (seq, seq),
+            Packet::Repair { first, count, .. } => {
+                (first, first.wrapping_add(u32::from(count) - 1))
+            }
+        };
+        let next_seq = *self.next_seq.get_or_insert_with(|| {
+            self.newest = newest;
+            first
+        });
+        if is_behind(newest, next_seq.wrapping_sub(u32::from(self.window))) {
+            return Ok(Vec::new());
+        }
+        if is_behind(self.newest, newest) {
+            self.newest = newest;
+        }
+
+        let released = py.allow_threads(|| {
+            match packet {
+                Packet::Source { seq, payload } => self.add_source(seq, shard(payload)),
+                Packet::Repair {
+                    first,
+                    count,
+                    repair_seq,
+                    body,
+                } => self.add_repair(first, count, repair_seq, body),
+            }
+            self.release(false)
+        });
+        Ok(released
+            .iter()
+            .map(|payload| PyBytes::new(py, payload))
+            .collect())
+    }
+
+    /// Gives up on all missing payloads, e.g. at the end of the stream, and
+    /// returns the remaining received ones in order.
+    fn flush<'py>(&mut self, py: Python<'py>) -> Vec<&'py PyBytes> {
+        self.release(true)
+            .iter()
+            .map(|payload| PyBytes::new(py, payload))
+            .collect()
+    }
+}
+
+impl SlidingWindowReceiver {
+    fn add_source(&mut self, seq: u32, shard: Vec<u8>) {
+        if self.sources.contains_key(&seq) {
+            return;
+        }
+        for equation in &mut self.equations {
+            equation.substitute(seq, &shard);
+        }
+        self.sources.insert(seq, shard);
+        self.solve();
+    }
+
+    fn add_repair(&mut self, first: u32, count: u16, repair_seq: u32, body: &[u8]) {
+        let mut equation = Equation {
+            terms: BTreeMap::new(),
+            data: body.to_vec(),
+        };
+        for seq in (0..u32::from(count)).map(|offset| first.wrapping_add(offset)) {
+            equation.terms.insert(seq, coefficient(repair_seq, seq));
+            if let Some(shard) = self.sources.get(&seq) {
+                equation.substitute(seq, shard);
+            }
+        }
+        if !equation.terms.is_empty() {
+            self.equations.push(equation);
+            self.solve();
+        }
+    }
+
+    fn solve(&mut self) {
+        for (seq, shard) in solve(&mut self.equations) {
+            // A solution with an invalid length is from inconsistent packets.
+            if read_shard(&shard).is_some() {
+                self.add_source(seq, shard);
+            }
+        }
+    }
+
+    /// Releases payloads in order, giving up on missing ones which no repair
+    /// packet still to be sent covers, or on all if `give_up` is set.
+    fn release(&mut self, give_up: bool) -> Vec<Vec<u8>> {
+        let Some(mut next_seq) = self.next_seq else {
+            return Vec::new();
+        };
+        // Missing payloads before `limit` are given up on.
+        let limit = if give_up {
+            self.newest.wrapping_add(1)
+        } else {
+            self.newest.wrapping_sub(u32::from(self.window) - 1)
+        };
+        let mut released = Vec::new();
+        loop {
+            if let Some(shard) = self.sources.get(&next_seq) {
+                released.push(read_shard(shard).expect("checked on insert").to_vec());
+                next_seq = next_seq.wrapping_add(1);
+            } else if is_behind(next_seq, limit) {
+                // Skip the whole run of missing payloads at once, as a jump
+                // in sequence numbers makes it arbitrarily long.
+                let run_end = self
+                    .sources
+                    .keys()
+                    .copied()
+                    .filter(|&seq| is_behind(next_seq, seq) && is_behind(seq, limit))
+                    .min_by_key(|&seq| seq.wrapping_sub(next_seq))
+                    .unwrap_or(limit);
+                self.lost += run_end.wrapping_sub(next_seq) as usize;
+                next_seq = run_end;
+            } else {
+                break;
+            }
+        }
+        self.next_seq = Some(next_seq);
+
+        let oldest_kept = next_seq.wrapping_sub(u32::from(self.window));
+        self.sources.retain(|&seq, _| !is_behind(seq, oldest_kept));
+        self.equations
+            .retain(|equation| equation.terms.keys().any(|&seq| !is_behind(seq, next_seq)));
+        released
+    }
+}
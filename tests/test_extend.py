@@ -0,0 +1,43 @@
+#!/usr/bin/env python3
+
+# Checks that recovery shards of the growing code from separate calls to
+# `encode_recovery_range` decode together with `decode_extended`.
+
+import random
+
+import reed_solomon_leopard
+
+
+def test_decode_mix_of_calls():
+    rng = random.Random(1)
+    for original_count, shard_bytes in [(1, 64), (8, 64), (10, 100), (100, 2)]:
+        original = [rng.randbytes(shard_bytes) for _ in range(original_count)]
+        # Recovery shards from an early call and from a later one which
+        # raises redundancy are the same as from a single call.
+        early = reed_solomon_leopard.encode_recovery_range(original, 0, 3)
+        later = reed_solomon_leopard.encode_recovery_range(original, 3, original_count)
+        whole = reed_solomon_leopard.encode_recovery_range(original, 0, 3 + original_count)
+        assert whole == early + later
+
+        recovery = dict(enumerate(early + later))
+        lost = rng.sample(range(original_count), rng.randrange(1, original_count + 1))
+        kept = {i: shard for i, shard in enumerate(original) if i not in lost}
+        # Decode with one recovery shard of the early call, the rest later.
+        used = {2: recovery[2]}
+        for i in rng.sample(range(3, len(recovery)), len(lost) - 1):
+            used[i] = recovery[i]
+        restored = reed_solomon_leopard.decode_extended(original_count, kept, used)
+        assert restored == {i: original[i] for i in lost}
+
+
+def test_separate_from_encode():
+    rng = random.Random(2)
+    original = [rng.randbytes(64) for _ in range(8)]
+    # `encode` uses the high rate code here, whose shards differ.
+    extended = reed_solomon_leopard.encode_recovery_range(original, 0, 3)
+    assert extended != reed_solomon_leopard.encode(original, 3)
+
+
+if __name__ == "__main__":
+    test_decode_mix_of_calls()
+    test_separate_from_encode()
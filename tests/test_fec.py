@@ -0,0 +1,171 @@
+#!/usr/bin/env python3
+
+# Checks that `FecReceiver` releases the payloads of a `FecSender` in order,
+# restores lost ones and keeps going after corrupted packets.
+
+import random
+import struct
+
+import reed_solomon_leopard
+
+ORIGINAL_COUNT = 4
+RECOVERY_COUNT = 2
+
+
+def send(payloads, flush_every=None):
+    """Returns the packets of each block, one list per block."""
+    sender = reed_solomon_leopard.FecSender(ORIGINAL_COUNT, RECOVERY_COUNT)
+    blocks = []
+    for i, payload in enumerate(payloads):
+        block = sender.block
+        packets = sender.send(payload)
+        if flush_every and i % flush_every == flush_every - 1:
+            packets += sender.flush()
+        if len(blocks) == block:
+            blocks.append([])
+        blocks[block] += packets
+    packets = sender.flush()
+    if packets:
+        blocks.append(packets)
+    return blocks
+
+
+def receive(packets, max_pending_blocks=8):
+    receiver = reed_solomon_leopard.FecReceiver(max_pending_blocks)
+    released = []
+    for packet in packets:
+        released += receiver.receive(packet)
+    released += receiver.flush()
+    return released, receiver.lost
+
+
+def random_payloads(rng, count):
+    return [rng.randbytes(rng.randrange(0, 200)) for _ in range(count)]
+
+
+def test_in_order():
+    rng = random.Random(1)
+    payloads = random_payloads(rng, 100)
+    for flush_every in [None, 3, 5]:
+        blocks = send(payloads, flush_every)
+        released, lost = receive([packet for block in blocks for packet in block])
+        assert released == payloads, flush_every
+        assert lost == 0
+
+
+def test_restores_random_loss():
+    rng = random.Random(2)
+    payloads = random_payloads(rng, 400)
+    for flush_every in [None, 3]:
+        blocks = send(payloads, flush_every)
+        # Up to `RECOVERY_COUNT` lost packets per block can be restored.
+        packets = []
+        for block in blocks:
+            lost = set(rng.sample(range(len(block)), rng.randrange(RECOVERY_COUNT + 1)))
+            packets += [packet for i, packet in enumerate(block) if i not in lost]
+        released, lost = receive(packets)
+        assert released == payloads, flush_every
+        assert lost == 0
+
+
+def test_counts_unrecoverable_loss():
+    rng = random.Random(3)
+    payloads = random_payloads(rng, 400)
+    packets = [packet for block in send(payloads) for packet in block]
+    received = [packet for packet in packets if rng.random() >= 0.3]
+    released, lost = receive(received)
+    # Released payloads keep their order, and each one sent is either
+    # released or counted as lost, unless its whole block was lost.
+    remaining = iter(payloads)
+    assert all(any(payload == sent for sent in remaining) for payload in released)
+    assert 0 < lost and len(released) + lost <= len(payloads)
+
+
+def test_reordered_start():
+    rng = random.Random(4)
+    payloads = random_payloads(rng, 40)
+    packets = [packet for block in send(payloads) for packet in block]
+    start = packets[:12]
+    rng.shuffle(start)
+    released, lost = receive(start + packets[12:])
+    assert released == payloads
+    assert lost == 0
+
+
+def test_flushed_block_without_recovery_packets():
+    sender = reed_solomon_leopard.FecSender(ORIGINAL_COUNT, RECOVERY_COUNT)
+    payloads = [bytes([i]) * (i + 1) for i in range(10)]
+    packets = []
+    for payload in payloads[:6]:
+        packets += sender.send(payload)
+    flushed = sender.flush()
+    # The block of 2 payloads loses both of its recovery packets.
+    packets += flushed[:-RECOVERY_COUNT]
+    later = []
+    for payload in payloads[6:]:
+        later += sender.send(payload)
+
+    receiver = reed_solomon_leopard.FecReceiver()
+    released = []
+    for packet in packets:
+        released += receiver.receive(packet)
+    assert released == payloads[:6]
+    for packet in later[:ORIGINAL_COUNT]:
+        released += receiver.receive(packet)
+    assert released == payloads
+    assert receiver.lost == 0
+
+
+def test_corrupted_packets():
+    receiver = reed_solomon_leopard.FecReceiver()
+    for packet in [b"", b"x" * 11, struct.pack("<IHHHH", 0, 0, 2, 1, 5) + b"z"]:
+        try:
+            receiver.receive(packet)
+        except ValueError:
+            pass
+        else:
+            raise AssertionError(f"accepted malformed packet {packet!r}")
+
+    rng = random.Random(5)
+    payloads = random_payloads(rng, 200)
+    blocks = send(payloads)
+    # A recovery packet with a wrong `recovery_count` and index doesn't
+    # match the others of its block.
+    block = blocks[0]
+    recovery = bytearray(block[ORIGINAL_COUNT + 1])
+    struct.pack_into("<H", recovery, 8, RECOVERY_COUNT + 1)
+    struct.pack_into("<H", recovery, 4, ORIGINAL_COUNT + RECOVERY_COUNT)
+    packets = [bytes(recovery)] + block[2 : ORIGINAL_COUNT + 1]
+    packets += [packet for block in blocks[1:] for packet in block]
+    released, lost = receive(packets)
+    # Whatever happens to block 0, all later blocks are still released.
+    assert released[-len(payloads) + ORIGINAL_COUNT :] == payloads[ORIGINAL_COUNT:]
+    assert len(released) + lost == len(payloads)
+
+    # Bit flips in the index, counts or length of a packet must raise
+    # `ValueError` or lose at most payloads of its own block. A flipped
+    # block id can't be told from a legitimate packet of another block.
+    receiver = reed_solomon_leopard.FecReceiver()
+    released = []
+    for block in blocks:
+        for packet in block:
+            if rng.random() < 0.05:
+                packet = bytearray(packet)
+                packet[rng.randrange(4, 12)] ^= 1 << rng.randrange(8)
+            try:
+                released += receiver.receive(bytes(packet))
+            except ValueError:
+                pass
+    released += receiver.flush()
+    remaining = iter(payloads)
+    assert all(any(payload == sent for sent in remaining) for payload in released)
+    assert released[-10:] == payloads[-10:]
+
+
+if __name__ == "__main__":
+    test_in_order()
+    test_restores_random_loss()
+    test_counts_unrecoverable_loss()
+    test_reordered_start()
+    test_flushed_block_without_recovery_packets()
+    test_corrupted_packets()
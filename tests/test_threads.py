@@ -0,0 +1,50 @@
+#!/usr/bin/env python3
+
+# Checks that encoding releases the GIL: two Python threads calling `encode`
+# at the same time must run in parallel, not one after the other.
+
+import os
+from threading import Thread
+from time import perf_counter
+
+import reed_solomon_leopard
+
+ORIGINAL_COUNT = 1024
+RECOVERY_COUNT = 1024
+SHARD_BYTES = 1024 * 64
+
+
+def timed_encode(original, spans, idx):
+    start = perf_counter()
+    reed_solomon_leopard.encode(original, RECOVERY_COUNT)
+    spans[idx] = (start, perf_counter())
+
+
+def test_encode_in_parallel():
+    if (os.cpu_count() or 1) < 2:
+        print("Skipped, needs at least two CPUs")
+        return
+
+    original = [os.urandom(SHARD_BYTES) for _ in range(ORIGINAL_COUNT)]
+
+    spans = [None, None]
+    timed_encode(original, spans, 0)
+    timed_encode(original, spans, 1)
+    serial = sum(end - start for start, end in spans)
+
+    threads = [Thread(target=timed_encode, args=(original, spans, idx)) for idx in range(2)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+    (start_a, end_a), (start_b, end_b) = spans
+    overlap = min(end_a, end_b) - max(start_a, start_b)
+    parallel = max(end_a, end_b) - min(start_a, start_b)
+
+    print(f"Two encodes took {parallel:.3f}s in parallel, {serial:.3f}s one after the other")
+    assert overlap > 0, "encode calls didn't overlap"
+    assert parallel < serial * 0.75, "encode holds the GIL"
+
+
+if __name__ == "__main__":
+    test_encode_in_parallel()
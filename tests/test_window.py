@@ -0,0 +1,149 @@
+#!/usr/bin/env python3
+
+# Checks that `SlidingWindowReceiver` releases the payloads of a
+# `SlidingWindowSender` in order, restores lost ones, gives up on those it
+# can't restore and rejects malformed packets.
+
+import random
+import struct
+import time
+
+import reed_solomon_leopard
+
+WINDOW = 16
+
+
+def send(payloads, repair_interval=2, repair_count=1):
+    sender = reed_solomon_leopard.SlidingWindowSender(WINDOW, repair_interval, repair_count)
+    packets = []
+    for payload in payloads:
+        packets += sender.send(payload)
+    return packets + sender.flush()
+
+
+def receive(packets):
+    receiver = reed_solomon_leopard.SlidingWindowReceiver(WINDOW)
+    released = []
+    for packet in packets:
+        released += receiver.receive(packet)
+    released += receiver.flush()
+    return released, receiver.lost
+
+
+def is_source(packet):
+    return struct.unpack_from("<H", packet, 4)[0] == 0
+
+
+def random_payloads(rng, count):
+    return [rng.randbytes(rng.randrange(0, 200)) for _ in range(count)]
+
+
+def test_in_order():
+    rng = random.Random(1)
+    payloads = random_payloads(rng, 200)
+    released, lost = receive(send(payloads))
+    assert released == payloads
+    assert lost == 0
+
+
+def test_restores_lost_packets():
+    rng = random.Random(2)
+    payloads = random_payloads(rng, 400)
+    packets = send(payloads)
+    # One repair packet per two source packets restores isolated losses.
+    sources = [i for i, packet in enumerate(packets) if is_source(packet)]
+    lost = set(sources[1::5])
+    released, lost = receive([p for i, p in enumerate(packets) if i not in lost])
+    assert released == payloads
+    assert lost == 0
+
+    # A run of lost source packets is restored from later repair packets.
+    lost = set(sources[100:104])
+    released, lost = receive([p for i, p in enumerate(packets) if i not in lost])
+    assert released == payloads
+    assert lost == 0
+
+
+def test_gives_up_on_lost_packets():
+    rng = random.Random(3)
+    payloads = random_payloads(rng, 100)
+    # Without repair packets nothing can be restored.
+    packets = [packet for packet in send(payloads) if is_source(packet)]
+    received = packets[:10] + packets[20:]
+    receiver = reed_solomon_leopard.SlidingWindowReceiver(WINDOW)
+    released = []
+    for i, packet in enumerate(received):
+        released += receiver.receive(packet)
+        # Payloads are given up on once the window moved past them.
+        if i == 10 + WINDOW - 2:
+            assert receiver.lost == 9 and len(released) == 10
+        if i == 10 + WINDOW - 1:
+            assert receiver.lost == 10 and len(released) == 10 + WINDOW
+    released += receiver.flush()
+    assert released == payloads[:10] + payloads[20:]
+    assert receiver.lost == 10
+
+    # Random loss too heavy to restore everything.
+    packets = send(payloads)
+    received = [packet for packet in packets if rng.random() >= 0.5]
+    released, lost = receive(received)
+    remaining = iter(payloads)
+    assert all(any(payload == sent for sent in remaining) for payload in released)
+    assert 0 < lost and len(released) + lost <= len(payloads)
+
+
+def test_sequence_jump():
+    sender = reed_solomon_leopard.SlidingWindowSender(WINDOW)
+    receiver = reed_solomon_leopard.SlidingWindowReceiver(WINDOW)
+    assert receiver.receive(sender.send(b"first")[0]) == [b"first"]
+    # A source packet far ahead gives up on all skipped ones in one step.
+    start = time.monotonic()
+    jumped = struct.pack("<IHH", 1 << 30, 0, 4) + b"next"
+    assert receiver.receive(jumped) == []
+    assert receiver.lost == (1 << 30) - 1 - WINDOW + 1
+    assert receiver.flush() == [b"next"]
+    assert receiver.lost == (1 << 30) - 1
+    assert time.monotonic() - start < 1
+
+
+def test_malformed_packets():
+    malformed = [
+        b"",
+        b"x" * 7,
+        # Body length disagrees with the packet.
+        struct.pack("<IHH", 0, 0, 5) + b"abcd",
+        # Repair packet with its header cut short.
+        struct.pack("<IHH", 0, 1, 0),
+        # Repair packet with an odd body length.
+        struct.pack("<IHHI", 0, 1, 3, 0) + b"abc",
+        # Repair packet covering more than the window.
+        struct.pack("<IHHI", 0, WINDOW + 1, 2, 0) + b"ab",
+    ]
+    receiver = reed_solomon_leopard.SlidingWindowReceiver(WINDOW)
+    for packet in malformed:
+        try:
+            receiver.receive(packet)
+        except ValueError:
+            pass
+        else:
+            raise AssertionError(f"accepted malformed packet {packet!r}")
+
+    # A corrupted repair body restores garbage, or nothing if its length
+    # field is invalid, but later payloads are still released.
+    rng = random.Random(4)
+    payloads = random_payloads(rng, 100)
+    packets = send(payloads)
+    repair = next(i for i, packet in enumerate(packets) if not is_source(packet))
+    packets[repair] = packets[repair][:12] + bytes(len(packets[repair]) - 12)
+    del packets[repair - 1]
+    released, lost = receive(packets)
+    assert released[-90:] == payloads[-90:]
+    assert len(released) + lost == len(payloads)
+
+
+if __name__ == "__main__":
+    test_in_order()
+    test_restores_lost_packets()
+    test_gives_up_on_lost_packets()
+    test_sequence_jump()
+    test_malformed_packets()